@@ -1,6 +1,77 @@
-extern crate gcc;
+#[cfg(feature = "enabled")]
+extern crate cc;
 
-fn main() {
-    gcc::compile_library("libremotery.a", &["external/remotery/lib/Remotery.c"]);
+#[cfg(feature = "enabled")]
+use std::{env, fs, io::Write, path::{Path, PathBuf}};
+
+// Lists all files below ``dir`` with their path relative to ``root`` using ``/`` as separator
+#[cfg(feature = "enabled")]
+fn viewer_files(root: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+
+        if path.is_dir() {
+            viewer_files(root, &path, files);
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let components: Vec<_> = relative.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+            files.push((format!("/{}", components.join("/")), path.clone()));
+        }
+    }
 }
 
+// The vendored viewer in external/remotery/vis, or a custom one in ``REMOTERY_VIS_DIR``
+#[cfg(feature = "enabled")]
+fn viewer_dir() -> PathBuf {
+    let dir = match env::var_os("REMOTERY_VIS_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("external/remotery/vis"),
+    };
+
+    if !dir.join("index.html").is_file() {
+        panic!("Remotery viewer {} has no index.html", dir.display());
+    }
+
+    dir
+}
+
+// Embeds the viewer files so they can be served over HTTP
+#[cfg(feature = "enabled")]
+fn embed_viewer() {
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let vis = viewer_dir();
+
+    let mut files = Vec::new();
+    viewer_files(&vis, &vis, &mut files);
+    files.sort();
+
+    let mut assets = fs::File::create(out.join("viewer_assets.rs")).unwrap();
+    writeln!(assets, "static ASSETS: &[(&str, &[u8])] = &[").unwrap();
+
+    for (name, path) in files {
+        writeln!(assets, "    ({:?}, include_bytes!({:?})),", name, path).unwrap();
+    }
+
+    writeln!(assets, "];").unwrap();
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=external/remotery");
+    println!("cargo:rerun-if-env-changed=REMOTERY_VIS_DIR");
+
+    // Nothing of the C lib is needed when profiling has been compiled out
+    #[cfg(feature = "enabled")]
+    {
+        // Remotery.c is mostly upstream code, its warnings are left to upstream
+        cc::Build::new()
+            .file("external/remotery/lib/Remotery.c")
+            .warnings(false)
+            .compile("remotery");
+        embed_viewer();
+    }
+}
@@ -11,7 +11,7 @@ fn some_function() {
 
 fn main() {
     let _remotery = Remotery::create_global_instance().unwrap_or_else(|e| {
-    	panic!(e);
+    	panic!("{}", e);
 	});
 
     for _ in 0..1000 {
@@ -0,0 +1,59 @@
+extern crate remotery;
+
+use remotery::{profile, Remotery};
+use std::time::Duration;
+use std::thread;
+
+struct World {
+    entities: Vec<u32>,
+}
+
+trait System {
+    fn run(&mut self, world: &mut World);
+}
+
+struct Physics;
+
+impl System for Physics {
+    #[profile]
+    fn run(&mut self, world: &mut World) {
+        for entity in &mut world.entities {
+            *entity += 1;
+        }
+    }
+}
+
+impl World {
+    #[profile(name = "World::update")]
+    fn update(&mut self, systems: &mut [Box<dyn System>]) {
+        for system in systems.iter_mut() {
+            system.run(self);
+        }
+
+        sum(&self.entities);
+    }
+}
+
+#[profile(aggregate)]
+fn sum<T: Copy + Into<u64>>(values: &[T]) -> u64 {
+    values.iter().map(|v| (*v).into()).sum()
+}
+
+#[profile]
+fn frame(world: &mut World, systems: &mut [Box<dyn System>]) {
+    world.update(systems);
+    thread::sleep(Duration::from_millis(16));
+}
+
+fn main() {
+    let _remotery = Remotery::create_global_instance().unwrap_or_else(|e| {
+        panic!("{}", e);
+    });
+
+    let mut world = World { entities: vec![0; 1000] };
+    let mut systems: Vec<Box<dyn System>> = vec![Box::new(Physics)];
+
+    for _ in 0..1000 {
+        frame(&mut world, &mut systems);
+    }
+}
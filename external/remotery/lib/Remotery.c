@@ -97,6 +97,7 @@ static rmtBool g_SettingsInitialized = RMT_FALSE;
 
     #ifdef RMT_PLATFORM_WINDOWS
         #include <winsock2.h>
+        #include <ws2tcpip.h>
         #ifndef __MINGW32__
             #include <intrin.h>
         #endif
@@ -117,6 +118,7 @@ static rmtBool g_SettingsInitialized = RMT_FALSE;
         #include <sys/socket.h>
         #include <sys/mman.h>
         #include <netinet/in.h>
+        #include <netdb.h>
         #include <fcntl.h>
         #include <errno.h>
         #include <dlfcn.h>
@@ -543,6 +545,8 @@ static void WriteFence()
     BeginNew(type, obj); error = type##_Constructor(obj, a0, a1); EndNew(type, obj)
 #define New_3(type, obj, a0, a1, a2)    \
     BeginNew(type, obj); error = type##_Constructor(obj, a0, a1, a2); EndNew(type, obj)
+#define New_5(type, obj, a0, a1, a2, a3, a4)    \
+    BeginNew(type, obj); error = type##_Constructor(obj, a0, a1, a2, a3, a4); EndNew(type, obj)
 
 
 
@@ -1771,22 +1775,54 @@ static void TCPSocket_Destructor(TCPSocket* tcp_socket)
 }
 
 
-static rmtError TCPSocket_RunServer(TCPSocket* tcp_socket, rmtU16 port, rmtBool limit_connections_to_localhost)
+static rmtError TCPSocket_RunServer(TCPSocket* tcp_socket, rmtU16 port, rmtBool limit_connections_to_localhost, rmtPStr bind_address, rmtBool dual_stack)
 {
     SOCKET s = INVALID_SOCKET;
-    struct sockaddr_in sin;
+    struct addrinfo hints;
+    struct addrinfo* address = NULL;
+    char port_string[8];
+    char* port_ptr;
+    rmtU32 value;
     int enable = 1;
     #ifdef RMT_PLATFORM_WINDOWS
         u_long nonblock = 1;
     #endif
 
-    memset(&sin, 0, sizeof(sin) );
     assert(tcp_socket != NULL);
 
+    // Without an explicit address listen on all IPv4 interfaces or only on localhost
+    if (bind_address == NULL)
+        bind_address = limit_connections_to_localhost ? "127.0.0.1" : "0.0.0.0";
+
+    // Port in radix 10 for getaddrinfo, 0 lets the OS pick a free port
+    port_ptr = port_string + sizeof(port_string) - 1;
+    *port_ptr = 0;
+    value = port;
+    do
+    {
+        *--port_ptr = (char)('0' + value % 10);
+        value /= 10;
+    } while (value);
+
+    // Numeric addresses only so this never blocks on a name lookup, IPv4 and IPv6 are both accepted
+    memset(&hints, 0, sizeof(hints));
+    hints.ai_family = AF_UNSPEC;
+    hints.ai_socktype = SOCK_STREAM;
+    hints.ai_protocol = IPPROTO_TCP;
+    hints.ai_flags = AI_PASSIVE | AI_NUMERICHOST;
+    if (getaddrinfo(bind_address, port_ptr, &hints, &address) != 0 || address == NULL)
+        return RMT_ERROR_SOCKET_BIND_FAIL;
+
     // Try to create the socket
-    s = socket(AF_INET, SOCK_STREAM, IPPROTO_TCP);
+    s = socket(address->ai_family, address->ai_socktype, address->ai_protocol);
     if (s == SOCKET_ERROR)
+    {
+        freeaddrinfo(address);
         return RMT_ERROR_SOCKET_CREATE_FAIL;
+    }
+
+    // Owned by the TCPSocket from here on so it gets closed on failure
+    tcp_socket->socket = s;
 
     // set SO_REUSEADDR so binding doesn't fail when restarting the application
     // (otherwise the same port can't be reused within TIME_WAIT)
@@ -1802,15 +1838,21 @@ static rmtError TCPSocket_RunServer(TCPSocket* tcp_socket, rmtU16 port, rmtBool
         setsockopt(s, SOL_SOCKET, SO_EXCLUSIVEADDRUSE, (char *)&enable, sizeof(enable));
     #endif
 
+    // IPv6 sockets also accept IPv4 connections (as mapped addresses) when dual-stack, set either
+    // way as the default differs between platforms
+    if (address->ai_family == AF_INET6)
+    {
+        int v6_only = dual_stack ? 0 : 1;
+        setsockopt(s, IPPROTO_IPV6, IPV6_V6ONLY, (char*)&v6_only, sizeof(v6_only));
+    }
+
     // Bind the socket to the incoming port
-    sin.sin_family = AF_INET;
-    sin.sin_addr.s_addr = htonl(limit_connections_to_localhost ? INADDR_LOOPBACK : INADDR_ANY);
-    sin.sin_port = htons(port);
-    if (bind(s, (struct sockaddr*)&sin, sizeof(sin)) == SOCKET_ERROR)
+    if (bind(s, address->ai_addr, (int)address->ai_addrlen) == SOCKET_ERROR)
+    {
+        freeaddrinfo(address);
         return RMT_ERROR_SOCKET_BIND_FAIL;
-
-    // Connection is valid, remaining code is socket state modification
-    tcp_socket->socket = s;
+    }
+    freeaddrinfo(address);
 
     // Enter a listening state with a backlog of 1 connection
     if (listen(s, 1) == SOCKET_ERROR)
@@ -1829,6 +1871,31 @@ static rmtError TCPSocket_RunServer(TCPSocket* tcp_socket, rmtU16 port, rmtBool
 }
 
 
+static rmtBool TCPSocket_GetAddress(TCPSocket* tcp_socket, char* host, rmtU32 host_size, rmtU16* port)
+{
+    struct sockaddr_storage address;
+    socklen_t address_length = sizeof(address);
+
+    assert(tcp_socket != NULL);
+    assert(host != NULL);
+    assert(port != NULL);
+
+    // Reports the address the socket is bound to, including the port picked by the OS for port 0
+    if (getsockname(tcp_socket->socket, (struct sockaddr*)&address, &address_length) == SOCKET_ERROR)
+        return RMT_FALSE;
+
+    if (getnameinfo((struct sockaddr*)&address, address_length, host, host_size, NULL, 0, NI_NUMERICHOST) != 0)
+        return RMT_FALSE;
+
+    if (address.ss_family == AF_INET6)
+        *port = ntohs(((struct sockaddr_in6*)&address)->sin6_port);
+    else
+        *port = ntohs(((struct sockaddr_in*)&address)->sin_port);
+
+    return RMT_TRUE;
+}
+
+
 static void TCPSocket_Close(TCPSocket* tcp_socket)
 {
     assert(tcp_socket != NULL);
@@ -2020,11 +2087,12 @@ static rmtError TCPSocket_Send(TCPSocket* tcp_socket, const void* data, rmtU32 l
             //
             // As none of these are actually errors, we have to pass this timeout back to the caller.
             //
-            // TODO: This strategy breaks down if a send partially completes and then times out!
+            // A send that partially completed can't be retried or skipped without corrupting the
+            // stream so it's reported as a failure instead.
             //
             if (cur_ms - start_ms > timeout_ms)
             {
-                return RMT_ERROR_SOCKET_SEND_TIMEOUT;
+                return cur_data == (char*)data ? RMT_ERROR_SOCKET_SEND_TIMEOUT : RMT_ERROR_SOCKET_SEND_FAIL;
             }
         }
         else
@@ -2493,6 +2561,9 @@ typedef struct
     rmtU32 frame_bytes_remaining;
     rmtU32 mask_offset;
 
+    // Sends in a row that timed out without writing anything
+    rmtU32 nb_send_timeouts;
+
     union
     {
         rmtU8 mask[4];
@@ -2541,7 +2612,135 @@ static const char websocket_response[] =
     "Sec-WebSocket-Accept: ";
 
 
-static rmtError WebSocketHandshake(TCPSocket* tcp_socket, rmtPStr limit_host)
+static const char http_not_found[] =
+    "HTTP/1.1 404 Not Found\r\n"
+    "Content-Type: text/plain\r\n"
+    "Content-Length: 9\r\n"
+    "Connection: close\r\n"
+    "\r\n"
+    "Not Found";
+
+
+static rmtError HTTPServeFile(TCPSocket* tcp_socket, char* request)
+{
+    char header[256];
+    int header_len = sizeof(header) - 1;
+
+    char length_buffer[16];
+    char* length_ptr;
+
+    char* path;
+    char* path_end;
+    rmtPStr content_type = NULL;
+    const void* data = NULL;
+    rmtU32 length = 0;
+    rmtU32 value;
+    rmtError error;
+
+    assert(tcp_socket != NULL);
+    assert(g_Settings.http_handler != NULL);
+
+    // Path is everything after "GET " up to the query string or protocol version
+    path = request + 3;
+    while (*path == ' ')
+        path++;
+    path_end = path;
+    while (*path_end != 0 && *path_end != ' ' && *path_end != '?' && *path_end != '\r')
+        path_end++;
+
+    if (!g_Settings.http_handler(g_Settings.http_handler_context, path, (rmtU32)(path_end - path), &content_type, &data, &length))
+        return TCPSocket_Send(tcp_socket, http_not_found, sizeof(http_not_found) - 1, 1000);
+
+    // Content length in radix 10
+    length_ptr = length_buffer + sizeof(length_buffer) - 1;
+    *length_ptr = 0;
+    value = length;
+    do
+    {
+        *--length_ptr = (char)('0' + value % 10);
+        value /= 10;
+    } while (value);
+
+    header[0] = 0;
+    if (strncat_s(header, header_len, "HTTP/1.1 200 OK\r\nContent-Type: ", header_len) != EOK ||
+        strncat_s(header, header_len, content_type, header_len) != EOK ||
+        strncat_s(header, header_len, "\r\nContent-Length: ", header_len) != EOK ||
+        strncat_s(header, header_len, length_ptr, header_len) != EOK ||
+        strncat_s(header, header_len, "\r\nConnection: close\r\n\r\n", header_len) != EOK)
+        return RMT_ERROR_WEBSOCKET_HANDSHAKE_STRING_FAIL;
+
+    error = TCPSocket_Send(tcp_socket, header, (rmtU32)strnlen_s(header, header_len), 1000);
+    if (error != RMT_ERROR_NONE)
+        return error;
+
+    return TCPSocket_Send(tcp_socket, data, length, 1000);
+}
+
+
+static const char http_forbidden[] =
+    "HTTP/1.1 403 Forbidden\r\n"
+    "Content-Type: text/plain\r\n"
+    "Content-Length: 9\r\n"
+    "Connection: close\r\n"
+    "\r\n"
+    "Forbidden";
+
+
+// Looks at every character of the token so the time taken doesn't reveal how much of a guess
+// was right
+static rmtBool TokenMatches(const char* text, rsize_t text_length, rmtPStr token)
+{
+    rsize_t token_length = strnlen_s(token, 256);
+    rsize_t i;
+    rmtU8 difference = text_length != token_length ? 1 : 0;
+
+    for (i = 0; i < token_length; i++)
+        difference |= (rmtU8)(token[i] ^ (i < text_length ? text[i] : 0));
+
+    return difference == 0 ? RMT_TRUE : RMT_FALSE;
+}
+
+
+static rmtBool HasAccessToken(char* buffer, rsize_t buffer_len, rmtPStr token)
+{
+    char* value = buffer;
+    char* value_end;
+
+    // A "token" parameter in the query string of the request line, "GET /?token=... HTTP/1.1"
+    while (*value != 0 && *value != '\r' && *value != ' ')
+        value++;
+    while (*value == ' ')
+        value++;
+    while (*value != 0 && *value != '\r' && *value != ' ' && *value != '?')
+        value++;
+    while (*value == '?' || *value == '&')
+    {
+        value++;
+        value_end = value;
+        while (*value_end != 0 && *value_end != '\r' && *value_end != ' ' && *value_end != '&')
+            value_end++;
+
+        if (strncmp(value, "token=", 6) == 0 && TokenMatches(value + 6, value_end - value - 6, token))
+            return RMT_TRUE;
+
+        value = value_end;
+    }
+
+    // Or an "Authorization: Bearer ..." header
+    value = GetField(buffer, buffer_len, "Authorization:");
+    if (value == NULL || strncmp(value, "Bearer ", 7) != 0)
+        return RMT_FALSE;
+
+    value += 7;
+    value_end = value;
+    while (*value_end != 0 && *value_end != '\r')
+        value_end++;
+
+    return TokenMatches(value, value_end - value, token);
+}
+
+
+static rmtError WebSocketHandshake(TCPSocket* tcp_socket, rmtPStr limit_host, rmtBool* served_http)
 {
     rmtU32 start_ms, now_ms;
 
@@ -2602,6 +2801,21 @@ static rmtError WebSocketHandshake(TCPSocket* tcp_socket, rmtPStr limit_host)
     if (memcmp(buffer, "GET", 3) != 0)
         return RMT_ERROR_WEBSOCKET_HANDSHAKE_NOT_GET;
 
+    // Requests without a WebSocket key are plain HTTP requests for one of the viewer files
+    *served_http = RMT_FALSE;
+    if (g_Settings.http_handler != NULL && GetField(buffer, buffer_len, "Sec-WebSocket-Key:") == NULL)
+    {
+        *served_http = RMT_TRUE;
+        return HTTPServeFile(tcp_socket, buffer);
+    }
+
+    // Viewers have to prove they know the access token when one is set
+    if (g_Settings.access_token != NULL && !HasAccessToken(buffer, buffer_len, g_Settings.access_token))
+    {
+        TCPSocket_Send(tcp_socket, http_forbidden, sizeof(http_forbidden) - 1, 1000);
+        return RMT_ERROR_WEBSOCKET_HANDSHAKE_BAD_TOKEN;
+    }
+
     // Look for the version number and verify that it's supported
     version = GetField(buffer, buffer_len, "Sec-WebSocket-Version:");
     if (version == NULL)
@@ -2661,6 +2875,7 @@ static rmtError WebSocket_Constructor(WebSocket* web_socket, TCPSocket* tcp_sock
     web_socket->mode = WEBSOCKET_NONE;
     web_socket->frame_bytes_remaining = 0;
     web_socket->mask_offset = 0;
+    web_socket->nb_send_timeouts = 0;
     web_socket->data.mask[0] = 0;
     web_socket->data.mask[1] = 0;
     web_socket->data.mask[2] = 0;
@@ -2680,12 +2895,12 @@ static void WebSocket_Destructor(WebSocket* web_socket)
 }
 
 
-static rmtError WebSocket_RunServer(WebSocket* web_socket, rmtU16 port, rmtBool limit_connections_to_localhost, enum WebSocketMode mode)
+static rmtError WebSocket_RunServer(WebSocket* web_socket, rmtU16 port, rmtBool limit_connections_to_localhost, rmtPStr bind_address, rmtBool dual_stack, enum WebSocketMode mode)
 {
     // Create the server's listening socket
     assert(web_socket != NULL);
     web_socket->mode = mode;
-    return TCPSocket_RunServer(web_socket->tcp_socket, port, limit_connections_to_localhost);
+    return TCPSocket_RunServer(web_socket->tcp_socket, port, limit_connections_to_localhost, bind_address, dual_stack);
 }
 
 
@@ -2706,6 +2921,7 @@ static SocketStatus WebSocket_PollStatus(WebSocket* web_socket)
 static rmtError WebSocket_AcceptConnection(WebSocket* web_socket, WebSocket** client_socket)
 {
     TCPSocket* tcp_socket = NULL;
+    rmtBool served_http = RMT_FALSE;
     rmtError error;
 
     // Is there a waiting connection?
@@ -2716,9 +2932,17 @@ static rmtError WebSocket_AcceptConnection(WebSocket* web_socket, WebSocket** cl
 
     // Need a successful handshake between client/server before allowing the connection
     // TODO: Specify limit_host
-    error = WebSocketHandshake(tcp_socket, NULL);
-    if (error != RMT_ERROR_NONE)
-        return error;
+    // HTTP requests are answered in full and closed straight away. A failed handshake only drops
+    // this connection so it doesn't take down the listen socket and other pending connections.
+    error = WebSocketHandshake(tcp_socket, NULL, &served_http);
+    if (error != RMT_ERROR_NONE || served_http)
+    {
+        if (error == RMT_ERROR_WEBSOCKET_HANDSHAKE_BAD_TOKEN)
+            rmt_LogText("Rejected viewer connection without a valid access token");
+
+        Delete(TCPSocket, tcp_socket);
+        return RMT_ERROR_NONE;
+    }
 
     // Allocate and return a new client socket
     assert(client_socket != NULL);
@@ -2948,6 +3172,8 @@ typedef enum MessageID
     MsgID_NotReady,
     MsgID_LogText,
     MsgID_SampleTree,
+    MsgID_Raw,
+    MsgID_Frame,
 } MessageID;
 
 
@@ -3135,45 +3361,80 @@ typedef struct
 {
     WebSocket* listen_socket;
 
-    WebSocket* client_socket;
+    // Connected viewers, only the first nb_clients entries are valid
+    WebSocket** client_sockets;
+    rmtU32 nb_clients;
+    rmtU32 max_nb_clients;
 
     rmtU32 last_ping_time;
 
     rmtU16 port;
     rmtBool limit_connections_to_localhost;
+    rmtPStr bind_address;
+    rmtBool dual_stack;
+
+    // Numeric address the listen socket is bound to, empty until known
+    char bound_address[64];
 } Server;
 
 
-static rmtError Server_CreateListenSocket(Server* server, rmtU16 port, rmtBool limit_connections_to_localhost)
+static rmtError Server_CreateListenSocket(Server* server)
 {
     rmtError error = RMT_ERROR_NONE;
 
     New_1(WebSocket, server->listen_socket, NULL);
     if (error == RMT_ERROR_NONE)
-        error = WebSocket_RunServer(server->listen_socket, port, limit_connections_to_localhost, WEBSOCKET_TEXT);
+        error = WebSocket_RunServer(server->listen_socket, server->port, server->limit_connections_to_localhost, server->bind_address, server->dual_stack, WEBSOCKET_TEXT);
+
+    // Keep the address and the port picked by the OS for port 0 so recreating the socket doesn't
+    // move it
+    if (error == RMT_ERROR_NONE && server->bound_address[0] == 0)
+    {
+        if (TCPSocket_GetAddress(server->listen_socket->tcp_socket, server->bound_address, sizeof(server->bound_address), &server->port) == RMT_FALSE)
+            server->bound_address[0] = 0;
+    }
 
     return error;
 }
 
 
-static rmtError Server_Constructor(Server* server, rmtU16 port, rmtBool limit_connections_to_localhost)
+static rmtError Server_Constructor(Server* server, rmtU16 port, rmtBool limit_connections_to_localhost, rmtPStr bind_address, rmtBool dual_stack, rmtU32 max_nb_clients)
 {
     assert(server != NULL);
+    assert(max_nb_clients > 0);
     server->listen_socket = NULL;
-    server->client_socket = NULL;
+    server->client_sockets = NULL;
+    server->nb_clients = 0;
+    server->max_nb_clients = max_nb_clients;
     server->last_ping_time = 0;
     server->port = port;
     server->limit_connections_to_localhost = limit_connections_to_localhost;
+    server->bind_address = bind_address;
+    server->dual_stack = dual_stack;
+    server->bound_address[0] = 0;
+
+    server->client_sockets = (WebSocket**)rmtMalloc(max_nb_clients * sizeof(WebSocket*));
+    if (server->client_sockets == NULL)
+        return RMT_ERROR_MALLOC_FAIL;
 
     // Create the listening WebSocket
-    return Server_CreateListenSocket(server, port, limit_connections_to_localhost);
+    return Server_CreateListenSocket(server);
 }
 
 
 static void Server_Destructor(Server* server)
 {
     assert(server != NULL);
-    Delete(WebSocket, server->client_socket);
+    while (server->nb_clients > 0)
+    {
+        server->nb_clients--;
+        Delete(WebSocket, server->client_sockets[server->nb_clients]);
+    }
+    if (server->client_sockets != NULL)
+    {
+        rmtFree(server->client_sockets);
+        server->client_sockets = NULL;
+    }
     Delete(WebSocket, server->listen_socket);
 }
 
@@ -3181,41 +3442,63 @@ static void Server_Destructor(Server* server)
 static rmtBool Server_IsClientConnected(Server* server)
 {
     assert(server != NULL);
-    return server->client_socket != NULL ? RMT_TRUE : RMT_FALSE;
+    return server->nb_clients > 0 ? RMT_TRUE : RMT_FALSE;
 }
 
 
-static void Server_DisconnectClient(Server* server)
+static void Server_DisconnectClient(Server* server, rmtU32 index)
 {
     WebSocket* client_socket;
 
     assert(server != NULL);
+    assert(index < server->nb_clients);
 
-    // NULL the variable before destroying the socket
-    client_socket = server->client_socket;
-    server->client_socket = NULL;
+    // Move the last client into the free slot before destroying the socket
+    client_socket = server->client_sockets[index];
+    server->nb_clients--;
+    server->client_sockets[index] = server->client_sockets[server->nb_clients];
+    server->client_sockets[server->nb_clients] = NULL;
     WriteFence();
     Delete(WebSocket, client_socket);
 }
 
 
+// Number of sends in a row that can time out before a client is disconnected
+#define MAX_NB_SEND_TIMEOUTS 50
+
+
 static rmtError Server_Send(Server* server, const void* data, rmtU32 length, rmtU32 timeout)
 {
+    rmtError first_error = RMT_ERROR_NONE;
+    rmtU32 i;
+
     assert(server != NULL);
-    if (Server_IsClientConnected(server))
+
+    // Broadcast to all clients, walking backwards so disconnects don't skip anyone
+    for (i = server->nb_clients; i-- > 0; )
     {
-        rmtError error = WebSocket_Send(server->client_socket, data, length, timeout);
-        if (error == RMT_ERROR_SOCKET_SEND_FAIL)
-            Server_DisconnectClient(server);
+        WebSocket* client_socket = server->client_sockets[i];
+        rmtError error = WebSocket_Send(client_socket, data, length, timeout);
 
-        return error;
+        // A timeout means nothing of the frame was written so the client only misses this
+        // message. Clients that stay stalled are dropped so they don't slow down every send.
+        if (error == RMT_ERROR_SOCKET_SEND_TIMEOUT)
+            client_socket->nb_send_timeouts++;
+        else if (error == RMT_ERROR_NONE)
+            client_socket->nb_send_timeouts = 0;
+
+        if (error == RMT_ERROR_SOCKET_SEND_FAIL || client_socket->nb_send_timeouts >= MAX_NB_SEND_TIMEOUTS)
+            Server_DisconnectClient(server, i);
+
+        if (first_error == RMT_ERROR_NONE)
+            first_error = error;
     }
 
-    return RMT_ERROR_NONE;
+    return first_error;
 }
 
 
-static rmtError Server_ReceiveMessage(Server* server, char message_first_byte, rmtU32 message_length)
+static rmtError Server_ReceiveMessage(WebSocket* client_socket, char message_first_byte, rmtU32 message_length)
 {
     char message_data[1024];
     rmtError error;
@@ -3229,7 +3512,7 @@ static rmtError Server_ReceiveMessage(Server* server, char message_first_byte, r
 
     // Receive the rest of the message
     message_data[0] = message_first_byte;
-    error = WebSocket_Receive(server->client_socket, message_data + 1, NULL, message_length - 1, 100);
+    error = WebSocket_Receive(client_socket, message_data + 1, NULL, message_length - 1, 100);
     if (error != RMT_ERROR_NONE)
         return error;
     message_data[message_length] = 0;
@@ -3257,21 +3540,23 @@ static rmtError Server_ReceiveMessage(Server* server, char message_first_byte, r
 static void Server_Update(Server* server)
 {
     rmtU32 cur_time;
+    rmtU32 i;
 
     assert(server != NULL);
 
     // Recreate the listening socket if it's been destroyed earlier
     if (server->listen_socket == NULL)
-        Server_CreateListenSocket(server, server->port, server->limit_connections_to_localhost);
+        Server_CreateListenSocket(server);
 
-    if (server->listen_socket != NULL && server->client_socket == NULL)
+    if (server->listen_socket != NULL && server->nb_clients < server->max_nb_clients)
     {
-        // Accept connections as long as there is no client connected
+        // Accept connections as long as there are free client slots
         WebSocket* client_socket = NULL;
         rmtError error = WebSocket_AcceptConnection(server->listen_socket, &client_socket);
         if (error == RMT_ERROR_NONE)
         {
-            server->client_socket = client_socket;
+            if (client_socket != NULL)
+                server->client_sockets[server->nb_clients++] = client_socket;
         }
         else
         {
@@ -3281,18 +3566,19 @@ static void Server_Update(Server* server)
         }
     }
 
-    else
+    // Check each client for incoming messages, walking backwards so disconnects don't skip anyone
+    for (i = server->nb_clients; i-- > 0; )
     {
-        // Check for any incoming messages
+        WebSocket* client_socket = server->client_sockets[i];
         char message_first_byte;
         rmtU32 message_length;
-        rmtError error = WebSocket_Receive(server->client_socket, &message_first_byte, &message_length, 1, 0);
+        rmtError error = WebSocket_Receive(client_socket, &message_first_byte, &message_length, 1, 0);
         if (error == RMT_ERROR_NONE)
         {
             // Parse remaining message
-            error = Server_ReceiveMessage(server, message_first_byte, message_length);
+            error = Server_ReceiveMessage(client_socket, message_first_byte, message_length);
             if (error != RMT_ERROR_NONE)
-                Server_DisconnectClient(server);
+                Server_DisconnectClient(server, i);
         }
         else if (error == RMT_ERROR_SOCKET_RECV_NO_DATA)
         {
@@ -3305,7 +3591,7 @@ static void Server_Update(Server* server)
         else
         {
             // Anything else is an error that may have closed the connection
-            Server_DisconnectClient(server);
+            Server_DisconnectClient(server, i);
         }
     }
 
@@ -3788,10 +4074,27 @@ typedef struct Msg_SampleTree
     ObjectAllocator* allocator;
 
     rmtPStr thread_name;
+
+    // Number of frames marked with _rmt_MarkFrame before the tree completed
+    rmtU32 frame_index;
 } Msg_SampleTree;
 
 
-static void AddSampleTreeMessage(MessageQueue* queue, Sample* sample, ObjectAllocator* allocator, rmtPStr thread_name, struct ThreadSampler* thread_sampler)
+// Index of the current frame, advanced by _rmt_MarkFrame
+static volatile rmtS32 g_FrameIndex = 0;
+
+
+typedef struct Msg_Frame
+{
+    rmtU32 frame_index;
+
+    // Start and length of the frame in microseconds
+    rmtU64 us_start;
+    rmtU64 us_length;
+} Msg_Frame;
+
+
+static void AddSampleTreeMessage(MessageQueue* queue, Sample* sample, ObjectAllocator* allocator, rmtPStr thread_name, rmtU32 frame_index, struct ThreadSampler* thread_sampler)
 {
     Msg_SampleTree* payload;
 
@@ -3809,6 +4112,7 @@ static void AddSampleTreeMessage(MessageQueue* queue, Sample* sample, ObjectAllo
     payload->root_sample = sample;
     payload->allocator = allocator;
     payload->thread_name = thread_name;
+    payload->frame_index = frame_index;
     MessageQueue_CommitMessage(message, MsgID_SampleTree);
 }
 
@@ -3901,7 +4205,7 @@ static rmtBool ThreadSampler_Pop(ThreadSampler* ts, MessageQueue* queue, Sample*
         root->first_child = NULL;
         root->last_child = NULL;
         root->nb_children = 0;
-        AddSampleTreeMessage(queue, sample, tree->allocator, ts->name, ts);
+        AddSampleTreeMessage(queue, sample, tree->allocator, ts->name, (rmtU32)g_FrameIndex, ts);
 
         return RMT_TRUE;
     }
@@ -4037,10 +4341,65 @@ static void GetSampleDigest(Sample* sample, rmtU32* digest_hash, rmtU32* nb_samp
 }
 
 
+static rmtError json_Frame(Buffer* buffer, Msg_Frame* msg)
+{
+    rmtError error;
+
+    assert(buffer != NULL);
+    assert(msg != NULL);
+
+    // Reset the buffer position to the start
+    buffer->bytes_used = 0;
+
+    JSON_ERROR_CHECK(json_OpenObject(buffer));
+
+        JSON_ERROR_CHECK(json_FieldStr(buffer, "id", "FRAME"));
+        JSON_ERROR_CHECK(json_Comma(buffer));
+        JSON_ERROR_CHECK(json_FieldU64(buffer, "frame_index", msg->frame_index));
+        JSON_ERROR_CHECK(json_Comma(buffer));
+        JSON_ERROR_CHECK(json_FieldU64(buffer, "us_start", msg->us_start));
+        JSON_ERROR_CHECK(json_Comma(buffer));
+        JSON_ERROR_CHECK(json_FieldU64(buffer, "us_length", msg->us_length));
+
+    return json_CloseObject(buffer);
+}
+
+
+static rmtError Remotery_SendFrameMessage(Remotery* rmt, Message* message)
+{
+    rmtError error = RMT_ERROR_NONE;
+    Msg_Frame* frame;
+
+    assert(rmt != NULL);
+    assert(message != NULL);
+
+    frame = (Msg_Frame*)message->payload;
+
+    if (g_Settings.frame_handler != NULL)
+        g_Settings.frame_handler(g_Settings.frame_handler_context, frame->frame_index, frame->us_start, frame->us_length);
+
+    if (Server_IsClientConnected(rmt->server) == RMT_TRUE)
+    {
+        error = json_Frame(rmt->json_buf, frame);
+        if (error == RMT_ERROR_NONE)
+            error = Server_Send(rmt->server, rmt->json_buf->data, rmt->json_buf->bytes_used, 20);
+    }
+
+    return error;
+}
+
+
 static rmtError Remotery_SendLogTextMessage(Remotery* rmt, Message* message)
 {
     assert(rmt != NULL);
     assert(message != NULL);
+
+    if (g_Settings.loghandler != NULL)
+    {
+        const char* thread_name = message->thread_sampler != NULL ? (const char*)message->thread_sampler->name : NULL;
+        g_Settings.loghandler(g_Settings.loghandler_context, thread_name, (const char*)message->payload, message->payload_size);
+    }
+
     return Server_Send(rmt->server, message->payload, message->payload_size, 20);
 }
 
@@ -4086,6 +4445,8 @@ static rmtError json_SampleTree(Buffer* buffer, Msg_SampleTree* msg)
         JSON_ERROR_CHECK(json_Comma(buffer));
         JSON_ERROR_CHECK(json_FieldU64(buffer, "sample_digest", digest_hash));
         JSON_ERROR_CHECK(json_Comma(buffer));
+        JSON_ERROR_CHECK(json_FieldU64(buffer, "frame_index", msg->frame_index));
+        JSON_ERROR_CHECK(json_Comma(buffer));
         JSON_ERROR_CHECK(json_SampleArray(buffer, root_sample, "samples"));
 
     JSON_ERROR_CHECK(json_CloseObject(buffer));
@@ -4124,7 +4485,7 @@ static rmtError Remotery_SendSampleTreeMessage(Remotery* rmt, Message* message)
         rmt_EndCPUSample();
         if (!are_samples_ready)
         {
-            AddSampleTreeMessage(rmt->mq_to_rmt_thread, sample, sample_tree->allocator, sample_tree->thread_name, message->thread_sampler);
+            AddSampleTreeMessage(rmt->mq_to_rmt_thread, sample, sample_tree->allocator, sample_tree->thread_name, sample_tree->frame_index, message->thread_sampler);
             return RMT_ERROR_NONE;
         }
 
@@ -4135,11 +4496,18 @@ static rmtError Remotery_SendSampleTreeMessage(Remotery* rmt, Message* message)
     }
     #endif
 
+    // Hand the tree to the user callback before it's released
+    if (g_Settings.sampletree_handler != NULL)
+        g_Settings.sampletree_handler(g_Settings.sampletree_context, sample_tree);
+
     // Serialise the sample tree and send to the viewer with a reasonably long timeout as the size
     // of the sample data may be large
-    error = json_SampleTree(rmt->json_buf, sample_tree);
-    if (error == RMT_ERROR_NONE)
-        error = Server_Send(rmt->server, rmt->json_buf->data, rmt->json_buf->bytes_used, 5000);
+    if (Server_IsClientConnected(rmt->server) == RMT_TRUE)
+    {
+        error = json_SampleTree(rmt->json_buf, sample_tree);
+        if (error == RMT_ERROR_NONE)
+            error = Server_Send(rmt->server, rmt->json_buf->data, rmt->json_buf->bytes_used, 5000);
+    }
 
     // Release the sample tree back to its allocator
     FreeSampleTree(sample, sample_tree->allocator);
@@ -4155,8 +4523,11 @@ static rmtError Remotery_ConsumeMessageQueue(Remotery* rmt)
 
     assert(rmt != NULL);
 
-    // Absorb as many messages in the queue while disconnected
-    if (Server_IsClientConnected(rmt->server) == RMT_FALSE)
+    // Absorb as many messages in the queue while disconnected, unless sample trees or log lines
+    // are also handed to a callback which needs to see them as they complete
+    if (Server_IsClientConnected(rmt->server) == RMT_FALSE &&
+        g_Settings.sampletree_handler == NULL &&
+        g_Settings.loghandler == NULL)
         return RMT_ERROR_NONE;
 
     // Loop reading the max number of messages for this update
@@ -4181,6 +4552,15 @@ static rmtError Remotery_ConsumeMessageQueue(Remotery* rmt)
             case MsgID_SampleTree:
                 error = Remotery_SendSampleTreeMessage(rmt, message);
                 break;
+
+            // Already serialised, passed on as is
+            case MsgID_Raw:
+                error = Server_Send(rmt->server, message->payload, message->payload_size, 20);
+                break;
+
+            case MsgID_Frame:
+                error = Remotery_SendFrameMessage(rmt, message);
+                break;
         }
 
         // Consume the message before reacting to any errors
@@ -4197,7 +4577,8 @@ static void Remotery_FlushMessageQueue(Remotery* rmt)
 {
     assert(rmt != NULL);
 
-    // Loop reading all remaining messages
+    // Loop reading all remaining messages. Nothing is sent to the viewers anymore but the user
+    // callbacks still get everything that was queued before shutdown.
     for (;;)
     {
         Message* message = MessageQueue_PeekNextMessage(rmt->mq_to_rmt_thread);
@@ -4208,13 +4589,31 @@ static void Remotery_FlushMessageQueue(Remotery* rmt)
         {
             // These can be safely ignored
             case MsgID_NotReady:
+            case MsgID_Raw:
+                break;
+
             case MsgID_LogText:
+                if (g_Settings.loghandler != NULL)
+                {
+                    const char* thread_name = message->thread_sampler != NULL ? (const char*)message->thread_sampler->name : NULL;
+                    g_Settings.loghandler(g_Settings.loghandler_context, thread_name, (const char*)message->payload, message->payload_size);
+                }
+                break;
+
+            case MsgID_Frame:
+                if (g_Settings.frame_handler != NULL)
+                {
+                    Msg_Frame* frame = (Msg_Frame*)message->payload;
+                    g_Settings.frame_handler(g_Settings.frame_handler_context, frame->frame_index, frame->us_start, frame->us_length);
+                }
                 break;
 
             // Release all samples back to their allocators
             case MsgID_SampleTree:
             {
                 Msg_SampleTree* sample_tree = (Msg_SampleTree*)message->payload;
+                if (g_Settings.sampletree_handler != NULL)
+                    g_Settings.sampletree_handler(g_Settings.sampletree_context, sample_tree);
                 FreeSampleTree(sample_tree->root_sample, sample_tree->allocator);
                 break;
             }
@@ -4281,6 +4680,9 @@ static rmtError Remotery_Constructor(Remotery* rmt)
     rmt->json_buf = NULL;
     rmt->thread = NULL;
 
+    // Frames are counted from the start of each instance
+    g_FrameIndex = 0;
+
     #if RMT_USE_CUDA
         rmt->cuda.CtxSetCurrent = NULL;
         rmt->cuda.EventCreate = NULL;
@@ -4307,7 +4709,7 @@ static rmtError Remotery_Constructor(Remotery* rmt)
         return error;
 
     // Create the server
-    New_2(Server, rmt->server, g_Settings.port, g_Settings.limit_connections_to_localhost);
+    New_5(Server, rmt->server, g_Settings.port, g_Settings.limit_connections_to_localhost, g_Settings.bind_address, g_Settings.bind_dual_stack, g_Settings.maxNbClients);
     if (error != RMT_ERROR_NONE)
         return error;
 
@@ -4490,6 +4892,18 @@ RMT_API rmtSettings* _rmt_Settings(void)
         g_Settings.input_handler = NULL;
         g_Settings.input_handler_context = NULL;
         g_Settings.logFilename = "rmtLog.txt";
+        g_Settings.sampletree_handler = NULL;
+        g_Settings.sampletree_context = NULL;
+        g_Settings.loghandler = NULL;
+        g_Settings.loghandler_context = NULL;
+        g_Settings.http_handler = NULL;
+        g_Settings.http_handler_context = NULL;
+        g_Settings.maxNbClients = 4;
+        g_Settings.bind_address = NULL;
+        g_Settings.bind_dual_stack = RMT_FALSE;
+        g_Settings.access_token = NULL;
+        g_Settings.frame_handler = NULL;
+        g_Settings.frame_handler_context = NULL;
 
         g_SettingsInitialized = RMT_TRUE;
     }
@@ -4536,6 +4950,20 @@ RMT_API Remotery* _rmt_GetGlobalInstance(void)
 }
 
 
+RMT_API rmtBool _rmt_GetServerAddress(Remotery* remotery, char* host, rmtU32 host_size, rmtU16* port)
+{
+    // Fixed once the instance is created, even when the OS picked the port
+    if (remotery == NULL || remotery->server == NULL || remotery->server->bound_address[0] == 0)
+        return RMT_FALSE;
+
+    if (strcpy_s(host, host_size, remotery->server->bound_address) != 0)
+        return RMT_FALSE;
+
+    *port = remotery->server->port;
+    return RMT_TRUE;
+}
+
+
 #ifdef RMT_PLATFORM_WINDOWS
     #pragma pack(push,8)
     typedef struct tagTHREADNAME_INFO
@@ -4629,6 +5057,62 @@ static rmtBool QueueLine(MessageQueue* queue, char* text, rmtU32 size, struct Th
 static const char log_message[] = "{ \"id\": \"LOG\", \"text\": \"";
 
 
+RMT_API void _rmt_SendRawMessage(rmtPStr json, rmtU32 length)
+{
+    Message* message;
+
+    if (g_Remotery == NULL || json == NULL)
+        return;
+
+    // Dropped like log lines when the queue is full
+    message = MessageQueue_AllocMessage(g_Remotery->mq_to_rmt_thread, length, NULL);
+    if (message == NULL)
+        return;
+
+    memcpy(message->payload, json, length);
+    MessageQueue_CommitMessage(message, MsgID_Raw);
+}
+
+
+RMT_API rmtU64 _rmt_GetTime(void)
+{
+    if (g_Remotery == NULL)
+        return 0;
+
+    return usTimer_Get(&g_Remotery->timer);
+}
+
+
+RMT_API rmtU32 _rmt_MarkFrame(void)
+{
+    if (g_Remotery == NULL)
+        return 0;
+
+    return (rmtU32)AtomicAdd(&g_FrameIndex, 1);
+}
+
+
+RMT_API void _rmt_SendFrame(rmtU32 frame_index, rmtU64 us_start, rmtU64 us_length)
+{
+    Message* message;
+    Msg_Frame* payload;
+
+    if (g_Remotery == NULL)
+        return;
+
+    // Dropped like log lines when the queue is full
+    message = MessageQueue_AllocMessage(g_Remotery->mq_to_rmt_thread, sizeof(Msg_Frame), NULL);
+    if (message == NULL)
+        return;
+
+    payload = (Msg_Frame*)message->payload;
+    payload->frame_index = frame_index;
+    payload->us_start = us_start;
+    payload->us_length = us_length;
+    MessageQueue_CommitMessage(message, MsgID_Frame);
+}
+
+
 RMT_API void _rmt_LogText(rmtPStr text)
 {
     int start_offset, prev_offset, i;
@@ -4776,6 +5260,76 @@ RMT_API void _rmt_EndCPUSample(void)
 }
 
 
+RMT_API const char* _rmt_SampleTreeGetThreadName(rmtSampleTree* sample_tree)
+{
+    assert(sample_tree != NULL);
+    return sample_tree->thread_name;
+}
+
+
+RMT_API rmtSample* _rmt_SampleTreeGetRootSample(rmtSampleTree* sample_tree)
+{
+    assert(sample_tree != NULL);
+    return sample_tree->root_sample;
+}
+
+
+RMT_API rmtU32 _rmt_SampleTreeGetFrameIndex(rmtSampleTree* sample_tree)
+{
+    assert(sample_tree != NULL);
+    return sample_tree->frame_index;
+}
+
+
+RMT_API const char* _rmt_SampleGetName(rmtSample* sample)
+{
+    assert(sample != NULL);
+    return sample->name;
+}
+
+
+RMT_API rmtU32 _rmt_SampleGetNameHash(rmtSample* sample)
+{
+    assert(sample != NULL);
+    return sample->name_hash;
+}
+
+
+RMT_API rmtU32 _rmt_SampleGetID(rmtSample* sample)
+{
+    assert(sample != NULL);
+    return sample->unique_id;
+}
+
+
+RMT_API rmtU64 _rmt_SampleGetStart(rmtSample* sample)
+{
+    assert(sample != NULL);
+    return sample->us_start;
+}
+
+
+RMT_API rmtU64 _rmt_SampleGetTime(rmtSample* sample)
+{
+    assert(sample != NULL);
+    return sample->us_length;
+}
+
+
+RMT_API rmtSample* _rmt_SampleGetFirstChild(rmtSample* sample)
+{
+    assert(sample != NULL);
+    return sample->first_child;
+}
+
+
+RMT_API rmtSample* _rmt_SampleGetNextSibling(rmtSample* sample)
+{
+    assert(sample != NULL);
+    return sample->next_sibling;
+}
+
+
 
 /*
 ------------------------------------------------------------------------------------------------------------------------
@@ -5505,7 +6059,7 @@ static void UpdateD3D11Frame(void)
 
         // Pass samples onto the remotery thread for sending to the viewer
         FreeD3D11TimeStamps(sample);
-        AddSampleTreeMessage(g_Remotery->mq_to_rmt_thread, sample, sample_tree->allocator, sample_tree->thread_name, message->thread_sampler);
+        AddSampleTreeMessage(g_Remotery->mq_to_rmt_thread, sample, sample_tree->allocator, sample_tree->thread_name, sample_tree->frame_index, message->thread_sampler);
         MessageQueue_ConsumeNextMessage(d3d11->mq_to_d3d11_main, message);
     }
 
@@ -6078,7 +6632,7 @@ static void UpdateOpenGLFrame(void)
 
         // Pass samples onto the remotery thread for sending to the viewer
         FreeOpenGLTimeStamps(sample);
-        AddSampleTreeMessage(g_Remotery->mq_to_rmt_thread, sample, sample_tree->allocator, sample_tree->thread_name, message->thread_sampler);
+        AddSampleTreeMessage(g_Remotery->mq_to_rmt_thread, sample, sample_tree->allocator, sample_tree->thread_name, sample_tree->frame_index, message->thread_sampler);
         MessageQueue_ConsumeNextMessage(opengl->mq_to_opengl_main, message);
     }
 
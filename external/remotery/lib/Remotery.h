@@ -240,6 +240,8 @@ typedef enum rmtError
     RMT_ERROR_OPENGL_ERROR,                     // Generic OpenGL error, no real need to expose more detail since app will probably have an OpenGL error callback registered
 
     RMT_ERROR_CUDA_UNKNOWN,
+
+    RMT_ERROR_WEBSOCKET_HANDSHAKE_BAD_TOKEN,    // WebSocket server handshake failed, missing or wrong access token
 } rmtError;
 
 
@@ -306,6 +308,14 @@ typedef void* (*rmtReallocPtr)(void* mm_context, void* ptr, rmtU32 size);
 typedef void (*rmtFreePtr)(void* mm_context, void* ptr);
 typedef void (*rmtInputHandlerPtr)(const char* text, void* context);
 
+// Opaque types handed to the sample tree callback, inspect with the _rmt_SampleTree/_rmt_Sample functions
+typedef struct Msg_SampleTree rmtSampleTree;
+typedef struct Sample rmtSample;
+typedef void (*rmtSampleTreeHandlerPtr)(void* context, rmtSampleTree* sample_tree);
+typedef void (*rmtLogHandlerPtr)(void* context, const char* thread_name, const char* message, rmtU32 length);
+typedef rmtBool (*rmtHttpHandlerPtr)(void* context, const char* path, rmtU32 path_length, rmtPStr* content_type, const void** data, rmtU32* length);
+typedef void (*rmtFrameHandlerPtr)(void* context, rmtU32 frame_index, rmtU64 us_start, rmtU64 us_length);
+
 
 // Struture to fill in to modify Remotery default settings
 typedef struct rmtSettings
@@ -345,6 +355,51 @@ typedef struct rmtSettings
     void* input_handler_context;
 
     rmtPStr logFilename;
+
+    // Callback pointer that receives each completed sample tree on the Remotery thread, whether
+    // a viewer is connected or not. The tree is only valid for the duration of the call.
+    rmtSampleTreeHandlerPtr sampletree_handler;
+
+    // Context pointer that gets sent to the sample tree callback function
+    void* sampletree_context;
+
+    // Callback pointer that receives each logged line on the Remotery thread as the JSON "LOG"
+    // message sent to the viewer (not null-terminated), whether a viewer is connected or not.
+    rmtLogHandlerPtr loghandler;
+
+    // Context pointer that gets sent to the log callback function
+    void* loghandler_context;
+
+    // Callback pointer that looks up the file to send back when a plain HTTP GET arrives on the
+    // server port instead of a WebSocket upgrade. The path is not null-terminated and the returned
+    // data must stay valid until the next call. Returning RMT_FALSE sends a 404.
+    rmtHttpHandlerPtr http_handler;
+
+    // Context pointer that gets sent to the HTTP callback function
+    void* http_handler_context;
+
+    // How many viewers can be connected at the same time, all of them receive the same data
+    rmtU32 maxNbClients;
+
+    // Numeric IPv4 or IPv6 address to listen on such as "192.168.1.10" or "::", overrides
+    // limit_connections_to_localhost when set. Use port 0 to have the OS pick a free port and
+    // _rmt_GetServerAddress to find out which one it was.
+    rmtPStr bind_address;
+
+    // Should an IPv6 bind_address also accept IPv4 connections?
+    rmtBool bind_dual_stack;
+
+    // Secret that viewers need to pass in the WebSocket handshake, either as a "token" query
+    // parameter or an "Authorization: Bearer" header. Connections without it are rejected.
+    // NULL allows anyone who can reach the port to connect.
+    rmtPStr access_token;
+
+    // Callback pointer that receives each frame queued with _rmt_SendFrame on the Remotery
+    // thread, whether a viewer is connected or not.
+    rmtFrameHandlerPtr frame_handler;
+
+    // Context pointer that gets sent to the frame callback function
+    void* frame_handler_context;
 } rmtSettings;
 
 
@@ -442,6 +497,7 @@ typedef struct rmtCUDABind
 
 // Types that end samples in their destructors
 extern "C" RMT_API void _rmt_EndCPUSample(void);
+
 struct rmt_EndCPUSampleOnScopeExit
 {
     ~rmt_EndCPUSampleOnScopeExit()
@@ -528,11 +584,32 @@ RMT_API enum rmtError _rmt_CreateGlobalInstance(Remotery** remotery);
 RMT_API void _rmt_DestroyGlobalInstance(Remotery* remotery);
 RMT_API void _rmt_SetGlobalInstance(Remotery* remotery);
 RMT_API Remotery* _rmt_GetGlobalInstance(void);
+RMT_API rmtBool _rmt_GetServerAddress(Remotery* remotery, char* host, rmtU32 host_size, rmtU16* port);
 RMT_API void _rmt_SetCurrentThreadName(rmtPStr thread_name);
 RMT_API void _rmt_LogText(rmtPStr text);
+// Queues a complete JSON message (not null-terminated) that is sent to all viewers as is
+RMT_API void _rmt_SendRawMessage(rmtPStr json, rmtU32 length);
+// Microseconds on the same timeline as the start of samples
+RMT_API rmtU64 _rmt_GetTime(void);
+// Ends the current frame and returns its index, sample trees completed after this get the next index
+RMT_API rmtU32 _rmt_MarkFrame(void);
+// Queues the "FRAME" message of a frame ended with _rmt_MarkFrame for the Remotery thread to send
+RMT_API void _rmt_SendFrame(rmtU32 frame_index, rmtU64 us_start, rmtU64 us_length);
 RMT_API void _rmt_BeginCPUSample(rmtPStr name, rmtU32 flags, rmtU32* hash_cache);
 RMT_API void _rmt_EndCPUSample(void);
 
+// Accessors for the sample trees received by rmtSettings.sampletree_handler
+RMT_API const char* _rmt_SampleTreeGetThreadName(rmtSampleTree* sample_tree);
+RMT_API rmtSample* _rmt_SampleTreeGetRootSample(rmtSampleTree* sample_tree);
+RMT_API rmtU32 _rmt_SampleTreeGetFrameIndex(rmtSampleTree* sample_tree);
+RMT_API const char* _rmt_SampleGetName(rmtSample* sample);
+RMT_API rmtU32 _rmt_SampleGetNameHash(rmtSample* sample);
+RMT_API rmtU32 _rmt_SampleGetID(rmtSample* sample);
+RMT_API rmtU64 _rmt_SampleGetStart(rmtSample* sample);
+RMT_API rmtU64 _rmt_SampleGetTime(rmtSample* sample);
+RMT_API rmtSample* _rmt_SampleGetFirstChild(rmtSample* sample);
+RMT_API rmtSample* _rmt_SampleGetNextSibling(rmtSample* sample);
+
 #if RMT_USE_CUDA
 RMT_API void _rmt_BindCUDA(const rmtCUDABind* bind);
 RMT_API void _rmt_BeginCUDASample(rmtPStr name, rmtU32* hash_cache, void* stream);
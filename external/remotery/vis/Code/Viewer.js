@@ -0,0 +1,280 @@
+//
+// Viewer for the Remotery WebSocket protocol as spoken by ../lib/Remotery.c. The server sends
+// JSON text messages:
+//
+//   SAMPLES   completed sample tree of one thread
+//   LOG       line logged with rmt_LogText
+//   FRAME     end of a frame marked with Remotery::mark_frame
+//   COUNTERS  snapshot of all counters and gauges
+//
+// and console input is sent back as "CONI" followed by the text.
+//
+(function()
+{
+    "use strict";
+
+    var DEFAULT_PORT = 17815;
+    var MAX_LOG_LINES = 500;
+    var MAX_FRAMES = 100;
+    var HISTORY = 100;
+
+    var socket = null;
+    var reconnect_timer = null;
+    var dirty = false;
+
+    var threads = { };
+    var counters = [ ];
+    var frame_lengths = [ ];
+
+    var address_input = document.getElementById("address");
+    var status_text = document.getElementById("status");
+    var pause_input = document.getElementById("pause");
+    var frames_text = document.getElementById("frames");
+    var threads_div = document.getElementById("threads");
+    var counters_body = document.querySelector("#counters tbody");
+    var log_div = document.getElementById("log");
+    var console_input = document.getElementById("console");
+
+
+    function DefaultAddress()
+    {
+        if (location.protocol == "http:" && location.host)
+            return location.host;
+
+        return "127.0.0.1:" + DEFAULT_PORT;
+    }
+
+
+    // The access token of the page is passed on so protected servers accept the connection
+    function SocketURL(address)
+    {
+        var token = new URLSearchParams(location.search).get("token");
+        var url = "ws://" + address + "/";
+
+        if (token)
+            url += "?token=" + encodeURIComponent(token);
+
+        return url;
+    }
+
+
+    function SetStatus(connected, text)
+    {
+        status_text.className = connected ? "connected" : "disconnected";
+        status_text.textContent = text;
+    }
+
+
+    function Connect()
+    {
+        clearTimeout(reconnect_timer);
+
+        if (socket)
+        {
+            socket.onclose = null;
+            socket.close();
+        }
+
+        var address = address_input.value;
+        SetStatus(false, "Connecting to " + address);
+
+        socket = new WebSocket(SocketURL(address));
+        socket.onopen = function() { SetStatus(true, "Connected to " + address); };
+        socket.onmessage = function(event) { OnMessage(event.data); };
+        socket.onclose = function()
+        {
+            SetStatus(false, "Disconnected");
+            reconnect_timer = setTimeout(Connect, 1000);
+        };
+    }
+
+
+    function OnMessage(data)
+    {
+        var message;
+
+        try
+        {
+            message = JSON.parse(data);
+        }
+        catch (e)
+        {
+            return;
+        }
+
+        switch (message.id)
+        {
+            case "SAMPLES":
+                OnSamples(message);
+                break;
+            case "LOG":
+                AddLogLine(message.text);
+                break;
+            case "FRAME":
+                frame_lengths.push(message.us_length);
+                if (frame_lengths.length > MAX_FRAMES)
+                    frame_lengths.shift();
+                break;
+            case "COUNTERS":
+                counters = message.counters;
+                break;
+        }
+
+        dirty = true;
+    }
+
+
+    // Keeps the latest tree of each thread and the recent lengths of each sample path in it
+    function OnSamples(message)
+    {
+        var thread = threads[message.thread_name];
+
+        if (!thread)
+        {
+            thread = threads[message.thread_name] = { root: null, history: { } };
+        }
+
+        if (pause_input.checked)
+            return;
+
+        thread.root = message.samples[0];
+
+        function AddHistory(sample, path)
+        {
+            path += "/" + sample.name;
+
+            var lengths = thread.history[path] || (thread.history[path] = [ ]);
+            lengths.push(sample.us_length);
+            if (lengths.length > HISTORY)
+                lengths.shift();
+
+            (sample.children || [ ]).forEach(function(child) { AddHistory(child, path); });
+        }
+
+        AddHistory(thread.root, "");
+    }
+
+
+    function AddLogLine(text)
+    {
+        var line = document.createElement("div");
+        line.textContent = text;
+        log_div.appendChild(line);
+
+        while (log_div.childNodes.length > MAX_LOG_LINES)
+            log_div.removeChild(log_div.firstChild);
+
+        log_div.scrollTop = log_div.scrollHeight;
+    }
+
+
+    function Ms(us)
+    {
+        return (us / 1000).toFixed(3);
+    }
+
+
+    function Cell(row, text)
+    {
+        var cell = document.createElement("td");
+        cell.textContent = text;
+        row.appendChild(cell);
+        return cell;
+    }
+
+
+    function RenderThread(name, thread)
+    {
+        var div = document.createElement("div");
+        div.className = "thread";
+
+        var title = document.createElement("h2");
+        title.textContent = name;
+        div.appendChild(title);
+
+        var table = document.createElement("table");
+        table.innerHTML = "<thead><tr><th>Sample</th><th>ms</th><th>self ms</th><th>avg ms</th><th>max ms</th></tr></thead>";
+        var body = document.createElement("tbody");
+        table.appendChild(body);
+        div.appendChild(table);
+
+        function AddRow(sample, depth, path)
+        {
+            path += "/" + sample.name;
+
+            var children = sample.children || [ ];
+            var children_us = children.reduce(function(total, child) { return total + child.us_length; }, 0);
+            var lengths = thread.history[path] || [ sample.us_length ];
+            var total = lengths.reduce(function(total, length) { return total + length; }, 0);
+
+            var row = document.createElement("tr");
+            var name_cell = Cell(row, "  ".repeat(depth));
+            var swatch = document.createElement("span");
+            swatch.className = "swatch";
+            swatch.style.background = sample.colour || "#888";
+            name_cell.appendChild(swatch);
+            name_cell.appendChild(document.createTextNode(sample.name));
+
+            Cell(row, Ms(sample.us_length));
+            Cell(row, Ms(Math.max(sample.us_length - children_us, 0)));
+            Cell(row, Ms(total / lengths.length));
+            Cell(row, Ms(Math.max.apply(null, lengths)));
+            body.appendChild(row);
+
+            children.forEach(function(child) { AddRow(child, depth + 1, path); });
+        }
+
+        AddRow(thread.root, 0, "");
+
+        return div;
+    }
+
+
+    function Render()
+    {
+        requestAnimationFrame(Render);
+
+        if (!dirty)
+            return;
+
+        dirty = false;
+
+        var names = Object.keys(threads).filter(function(name) { return threads[name].root; }).sort();
+        threads_div.textContent = "";
+        names.forEach(function(name) { threads_div.appendChild(RenderThread(name, threads[name])); });
+
+        counters_body.textContent = "";
+        counters.forEach(function(counter)
+        {
+            var row = document.createElement("tr");
+            Cell(row, counter.name);
+            Cell(row, String(counter.value));
+            counters_body.appendChild(row);
+        });
+
+        if (frame_lengths.length)
+        {
+            var mean = frame_lengths.reduce(function(total, length) { return total + length; }, 0) / frame_lengths.length;
+            frames_text.textContent = "Frame " + Ms(frame_lengths[frame_lengths.length - 1]) + " ms, " +
+                (mean > 0 ? (1000000 / mean).toFixed(1) : "0") + " fps";
+        }
+    }
+
+
+    address_input.value = DefaultAddress();
+    address_input.addEventListener("keydown", function(event) { if (event.key == "Enter") Connect(); });
+    document.getElementById("connect").addEventListener("click", Connect);
+
+    console_input.addEventListener("keydown", function(event)
+    {
+        if (event.key != "Enter" || !socket || socket.readyState != WebSocket.OPEN)
+            return;
+
+        socket.send("CONI" + console_input.value);
+        AddLogLine("> " + console_input.value);
+        console_input.value = "";
+    });
+
+    Connect();
+    requestAnimationFrame(Render);
+})();
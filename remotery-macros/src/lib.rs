@@ -0,0 +1,173 @@
+//! Attribute macros for [remotery](https://github.com/emoon/remotery-rs). Use them through the
+//! ``remotery`` crate (``#[remotery::profile]``) rather than depending on this crate directly.
+//!
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::{Error, Expr, ExprLit, ItemFn, Lit, LitStr, Meta, Token};
+
+struct Options {
+    name: Option<LitStr>,
+    aggregate: bool,
+}
+
+fn parse_options(args: TokenStream) -> Result<Options, Error> {
+    let mut options = Options {
+        name: None,
+        aggregate: false,
+    };
+
+    let metas = Punctuated::<Meta, Token![,]>::parse_terminated.parse(args)?;
+
+    for meta in metas {
+        match meta {
+            Meta::Path(ref path) if path.is_ident("aggregate") => options.aggregate = true,
+            Meta::NameValue(ref nv) if nv.path.is_ident("name") => match nv.value {
+                Expr::Lit(ExprLit { lit: Lit::Str(ref name), .. }) => options.name = Some(name.clone()),
+                ref value => return Err(Error::new_spanned(value, "expected a string literal")),
+            },
+            meta => return Err(Error::new_spanned(meta, "unknown option, expected `name = \"...\"` or `aggregate`")),
+        }
+    }
+
+    Ok(options)
+}
+
+fn expand(options: Options, mut function: ItemFn) -> Result<proc_macro2::TokenStream, Error> {
+    if let Some(asyncness) = function.sig.asyncness {
+        return Err(Error::new_spanned(asyncness, "#[profile] can't be used on async functions as the sample would be held across .await"));
+    }
+
+    if let Some(constness) = function.sig.constness {
+        return Err(Error::new_spanned(constness, "#[profile] can't be used on const functions"));
+    }
+
+    let flags = if options.aggregate {
+        quote!(::remotery::SampleFlags::Aggregate)
+    } else {
+        quote!(::remotery::SampleFlags::Default)
+    };
+
+    // The Self type of methods isn't visible to the attribute so the default name is looked up
+    // once the function is compiled
+    let scope = match options.name {
+        Some(name) => quote!(::remotery::scoped_cpu_sample!(#name, #flags);),
+        None => quote!(::remotery::__scoped_fn_sample!(#flags);),
+    };
+
+    let block = function.block;
+    function.block = Box::new(syn::parse_quote!({
+        #scope
+        #block
+    }));
+
+    Ok(quote!(#function))
+}
+
+///
+/// Profiles the whole body of a function. The sample is named from the module path, the Self type
+/// of methods and the name of the function (such as ``my_crate::physics::update``,
+/// ``my_crate::World::update`` or ``<my_crate::Physics as my_crate::System>::run``) unless a name
+/// is given. Works on free functions, methods and functions in trait impls, including generic
+/// ones.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[remotery::profile]
+/// fn update() {}
+///
+/// #[remotery::profile(name = "physics", aggregate)]
+/// fn step_physics() {}
+/// ```
+///
+#[proc_macro_attribute]
+pub fn profile(args: TokenStream, item: TokenStream) -> TokenStream {
+    let result = parse_options(args)
+        .and_then(|options| syn::parse::<ItemFn>(item).and_then(|function| expand(options, function)));
+
+    match result {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::ToTokens;
+    use syn::parse_quote;
+
+    fn options(name: Option<&str>, aggregate: bool) -> Options {
+        Options {
+            name: name.map(|name| LitStr::new(name, proc_macro2::Span::call_site())),
+            aggregate,
+        }
+    }
+
+    // Both sides are parsed again so ``>>`` and ``> >`` compare the same
+    fn assert_expands_to(options: Options, function: ItemFn, expected: proc_macro2::TokenStream) {
+        let normalize = |tokens| syn::parse2::<ItemFn>(tokens).unwrap().into_token_stream().to_string();
+        assert_eq!(normalize(expand(options, function).unwrap()), normalize(expected));
+    }
+
+    fn expect_error(function: ItemFn) -> String {
+        expand(options(None, false), function).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_free_function() {
+        let function = parse_quote!(fn update() { step(); });
+
+        assert_expands_to(options(None, false), function, quote!(fn update() {
+            ::remotery::__scoped_fn_sample!(::remotery::SampleFlags::Default);
+            { step(); }
+        }));
+    }
+
+    #[test]
+    fn test_method() {
+        let function = parse_quote!(pub fn update(&mut self, dt: f32) -> bool { true });
+
+        assert_expands_to(options(None, true), function, quote!(pub fn update(&mut self, dt: f32) -> bool {
+            ::remotery::__scoped_fn_sample!(::remotery::SampleFlags::Aggregate);
+            { true }
+        }));
+    }
+
+    #[test]
+    fn test_trait_impl_function_with_name() {
+        let function = parse_quote!(fn run(&self) {});
+
+        assert_expands_to(options(Some("Physics::run"), false), function, quote!(fn run(&self) {
+            ::remotery::scoped_cpu_sample!("Physics::run", ::remotery::SampleFlags::Default);
+            {}
+        }));
+    }
+
+    #[test]
+    fn test_generic_function() {
+        let function = parse_quote!(fn sum<T: Copy + Into<u64>>(values: &[T]) -> u64 where T: Default { 0 });
+
+        assert_expands_to(options(None, false), function, quote!(fn sum<T: Copy + Into<u64>>(values: &[T]) -> u64 where T: Default {
+            ::remotery::__scoped_fn_sample!(::remotery::SampleFlags::Default);
+            { 0 }
+        }));
+    }
+
+    #[test]
+    fn test_async_function_is_rejected() {
+        assert!(expect_error(parse_quote!(async fn load() {})).contains("can't be used on async functions"));
+    }
+
+    #[test]
+    fn test_const_function_is_rejected() {
+        assert!(expect_error(parse_quote!(const fn size() -> usize { 1 })).contains("can't be used on const functions"));
+    }
+}
@@ -0,0 +1,319 @@
+//!
+//! Connects to a running Remotery instance and records all sample trees and log lines to a
+//! capture file (see ``remotery::capture``), then prints a summary of the samples per name.
+//! Ctrl-C stops the recording the same way as reaching ``--duration`` so the capture file is
+//! complete and the summary still gets printed.
+//!
+//! ```text
+//! remotery-record --host 127.0.0.1 --port 17815 --out run.rmtcap --duration 30s
+//! ```
+//!
+extern crate remotery;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+#[cfg(windows)]
+use std::os::windows::io::AsRawSocket;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use remotery::capture::{self, Record};
+use remotery::client::Client;
+use remotery::stats::{self, Aggregator};
+
+const USAGE: &str = "\
+Usage: remotery-record --out <file> [options]
+
+Records sample trees and log lines from a running Remotery instance to a capture file.
+
+Options:
+    --host <host>          Host to connect to (default 127.0.0.1)
+    --port <port>          Port to connect to (default 17815)
+    --out <file>           Capture file to write
+    --token <token>        Access token if the server requires one
+    --duration <time>      How long to record such as 500ms, 30s, 5m or 1h
+                           (default until the connection is closed or Ctrl-C)
+    -h, --help             Print this help";
+
+/// How often the capture file is flushed so a crash loses at most this much of the recording
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Set once Ctrl-C was pressed
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+///
+/// Ctrl-C sets ``INTERRUPTED`` and shuts down the socket so the blocking ``Client::receive``
+/// returns. Only async-signal-safe calls are made from the handler, the default handler is
+/// restored so a second Ctrl-C terminates right away.
+///
+#[cfg(unix)]
+mod interrupt {
+    use std::os::raw::c_int;
+    use std::os::unix::io::RawFd;
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    const SIGINT: c_int = 2;
+    const SIG_DFL: usize = 0;
+    const SHUT_RDWR: c_int = 2;
+
+    static SOCKET: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" {
+        fn signal(signum: c_int, handler: usize) -> usize;
+        fn shutdown(socket: c_int, how: c_int) -> c_int;
+    }
+
+    extern "C" fn on_interrupt(_: c_int) {
+        super::INTERRUPTED.store(true, Ordering::SeqCst);
+
+        unsafe {
+            shutdown(SOCKET.load(Ordering::SeqCst), SHUT_RDWR);
+            signal(SIGINT, SIG_DFL);
+        }
+    }
+
+    pub fn install(socket: RawFd) {
+        SOCKET.store(socket, Ordering::SeqCst);
+
+        unsafe {
+            signal(SIGINT, on_interrupt as extern "C" fn(c_int) as usize);
+        }
+    }
+}
+
+///
+/// Same as the unix version with a console control handler, which Windows runs on a thread of
+/// its own.
+///
+#[cfg(windows)]
+mod interrupt {
+    use std::os::raw::c_int;
+    use std::os::windows::io::RawSocket;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    const CTRL_C_EVENT: u32 = 0;
+    const SD_BOTH: c_int = 2;
+
+    static SOCKET: AtomicU64 = AtomicU64::new(!0);
+
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: Option<extern "system" fn(u32) -> i32>, add: i32) -> i32;
+    }
+
+    #[link(name = "ws2_32")]
+    extern "system" {
+        fn shutdown(socket: usize, how: c_int) -> c_int;
+    }
+
+    extern "system" fn on_ctrl(event: u32) -> i32 {
+        if event != CTRL_C_EVENT || super::INTERRUPTED.swap(true, Ordering::SeqCst) {
+            return 0;
+        }
+
+        unsafe {
+            shutdown(SOCKET.load(Ordering::SeqCst) as usize, SD_BOTH);
+        }
+
+        1
+    }
+
+    pub fn install(socket: RawSocket) {
+        SOCKET.store(socket, Ordering::SeqCst);
+
+        unsafe {
+            SetConsoleCtrlHandler(Some(on_ctrl), 1);
+        }
+    }
+}
+
+struct Options {
+    host: String,
+    port: u16,
+    out: String,
+    token: Option<String>,
+    duration: Option<Duration>,
+}
+
+fn parse_duration(text: &str) -> Option<Duration> {
+    let split = text.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(text.len());
+    let value: f64 = text[..split].parse().ok()?;
+
+    let seconds = match &text[split..] {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 60.0 * 60.0,
+        _ => return None,
+    };
+
+    // Too large to fit a Duration
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+fn parse_options<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        host: "127.0.0.1".to_owned(),
+        port: 0x4597,
+        out: String::new(),
+        token: None,
+        duration: None,
+    };
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Err(String::new());
+        }
+
+        let value = args.next().ok_or_else(|| format!("Missing value for {}", arg))?;
+
+        match arg.as_str() {
+            "--host" => options.host = value,
+            "--port" => options.port = value.parse().map_err(|_| format!("Invalid port \"{}\"", value))?,
+            "--out" => options.out = value,
+            "--token" => options.token = Some(value),
+            "--duration" => options.duration = Some(parse_duration(&value).ok_or_else(|| format!("Invalid duration \"{}\"", value))?),
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+
+    if options.out.is_empty() {
+        return Err("Missing --out".to_owned());
+    }
+
+    Ok(options)
+}
+
+fn record(options: &Options) -> io::Result<()> {
+    let addr = (options.host.as_str(), options.port);
+    let mut client = match options.token {
+        Some(ref token) => Client::connect_with_token(addr, token)?,
+        None => Client::connect(addr)?,
+    };
+    let mut writer = capture::Writer::new(BufWriter::new(File::create(&options.out)?));
+    let mut aggregator = Aggregator::new();
+    let (mut nb_trees, mut nb_lines) = (0, 0);
+
+    let deadline = options.duration.map(|duration| Instant::now() + duration);
+    let mut last_flush = Instant::now();
+
+    #[cfg(unix)]
+    interrupt::install(client.as_raw_fd());
+    #[cfg(windows)]
+    interrupt::install(client.as_raw_socket());
+
+    eprintln!("Recording from {} to {}, press Ctrl-C to stop", client.peer_addr(), options.out);
+
+    loop {
+        if let Some(deadline) = deadline {
+            let now = Instant::now();
+
+            if now >= deadline {
+                break;
+            }
+
+            client.set_read_timeout(Some(deadline - now))?;
+        }
+
+        let message = match client.receive() {
+            Ok(message) => message,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => continue,
+            Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+                eprintln!("Skipping message: {}", e);
+                continue;
+            }
+            Err(_) if INTERRUPTED.load(Ordering::SeqCst) => {
+                eprintln!("Interrupted");
+                break;
+            }
+            Err(e) => {
+                eprintln!("Connection ended: {}", e);
+                break;
+            }
+        };
+
+        for tree in message.sample_trees() {
+            nb_trees += 1;
+            aggregator.add_tree(&tree);
+        }
+
+        if let Some(record) = Record::from_message(&message) {
+            if let Record::Log { .. } = record {
+                nb_lines += 1;
+            }
+
+            writer.write(&record)?;
+        }
+
+        if last_flush.elapsed() >= FLUSH_INTERVAL {
+            writer.flush()?;
+            last_flush = Instant::now();
+        }
+    }
+
+    writer.flush()?;
+
+    println!("Recorded {} sample trees and {} log lines to {}", nb_trees, nb_lines, options.out);
+    print!("{}", stats::format_table(&aggregator.report().names));
+
+    Ok(())
+}
+
+fn main() {
+    let options = parse_options(env::args().skip(1)).unwrap_or_else(|error| {
+        if !error.is_empty() {
+            eprintln!("{}\n", error);
+        }
+
+        eprintln!("{}", USAGE);
+        process::exit(if error.is_empty() { 0 } else { 2 });
+    });
+
+    if let Err(e) = record(&options) {
+        eprintln!("remotery-record: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Options, String> {
+        parse_options(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("1.5m"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2"), Some(Duration::from_secs(2)));
+        assert_eq!(parse_duration("2d"), None);
+        assert_eq!(parse_duration("s"), None);
+        assert_eq!(parse_duration(&format!("{}h", "9".repeat(20))), None);
+    }
+
+    #[test]
+    fn test_duration_overflow_is_an_error() {
+        assert_eq!(args(&["--out", "run.rmtcap", "--duration", "1e400"]).err().unwrap(), "Invalid duration \"1e400\"");
+
+        let huge = format!("{}h", "9".repeat(20));
+        assert_eq!(args(&["--out", "run.rmtcap", "--duration", &huge]).err().unwrap(), format!("Invalid duration \"{}\"", huge));
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = args(&["--port", "17816", "--out", "run.rmtcap", "--duration", "1h"]).unwrap();
+        assert_eq!((options.host.as_str(), options.port, options.out.as_str()), ("127.0.0.1", 17816, "run.rmtcap"));
+        assert_eq!(options.duration, Some(Duration::from_secs(3600)));
+        assert_eq!(options.token, None);
+        assert_eq!(args(&["--out", "run.rmtcap", "--token", "s3cret"]).unwrap().token.as_deref(), Some("s3cret"));
+
+        assert!(args(&["--port", "17816"]).is_err());
+        assert!(args(&["--out"]).is_err());
+        assert_eq!(args(&["--help"]).err(), Some(String::new()));
+    }
+}
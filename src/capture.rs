@@ -0,0 +1,449 @@
+//! Headless capture of profiling data to a file. With ``CaptureMode::File`` every sample tree and
+//! log line that the server would send to the viewer is also written to a capture file, even if no
+//! viewer ever connects. This makes it possible to profile CI and batch jobs and look at the
+//! results afterwards.
+//!
+//! The file has one JSON record per line. Each record matches the ``SAMPLES``/``LOG``/``COUNTERS``
+//! message sent to the viewer with the thread name and a ``timestamp`` (microseconds since the Unix
+//! epoch when the record was written) added.
+//!
+//! Records are buffered and flushed about once a second and when the instance is dropped, which
+//! also writes everything that was still queued for the Remotery thread.
+//!
+//! ```text
+//! {"id":"SAMPLES","timestamp":1700000000000000,"thread_name":"main","nb_samples":2,"samples":[{"name":"frame",...}]}
+//! {"id":"LOG","timestamp":1700000000000100,"thread_name":"main","text":"level loaded"}
+//! ```
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let settings = RemoterySettings::new().capture_mode(CaptureMode::File("profile.jsonl".into()));
+//! let _remotery = Remotery::create_global_instance_with(settings).unwrap();
+//!
+//! // ... later, possibly in another program
+//! for record in capture::read(File::open("profile.jsonl")?) {
+//!     println!("{:?}", record?);
+//! }
+//! ```
+//!
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "enabled")]
+use std::time::{Duration, Instant};
+use serde_json;
+use client::Message;
+use counters::CounterValue;
+use sample_tree::{Sample, SampleTree};
+#[cfg(feature = "enabled")]
+use std::ffi::CStr;
+#[cfg(feature = "enabled")]
+use std::fs::File;
+#[cfg(feature = "enabled")]
+use std::io::BufWriter;
+#[cfg(feature = "enabled")]
+use std::os::raw::{c_char, c_uint, c_void};
+#[cfg(feature = "enabled")]
+use std::path::Path;
+#[cfg(feature = "enabled")]
+use std::slice;
+#[cfg(feature = "enabled")]
+use std::sync::Mutex;
+#[cfg(feature = "enabled")]
+use error::RemoteryError;
+
+/// Where profiling data goes besides a connected viewer
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub enum CaptureMode {
+    /// Data is only sent to a connected viewer (default)
+    #[default]
+    Viewer,
+    /// Data is also written to a line-delimited JSON capture file at the path, which is created or
+    /// truncated when the instance is created
+    File(PathBuf),
+}
+
+/// A single line of a capture file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "id")]
+pub enum Record {
+    /// A completed sample tree of one thread
+    #[serde(rename = "SAMPLES")]
+    Samples {
+        /// Microseconds since the Unix epoch when the record was written
+        timestamp: u64,
+        /// Name of the thread the samples were taken on
+        thread_name: String,
+        /// Number of samples in the tree
+        nb_samples: u32,
+        /// Frame the tree belongs to, see ``Remotery::mark_frame``
+        #[serde(default)]
+        frame_index: u32,
+        /// The outermost sample (the viewer message allows for several, Remotery always sends one)
+        samples: Vec<Sample>,
+    },
+    /// A line sent with ``Remotery::log_text``
+    #[serde(rename = "LOG")]
+    Log {
+        /// Microseconds since the Unix epoch when the record was written
+        timestamp: u64,
+        /// Name of the thread that logged the text
+        thread_name: String,
+        /// The logged text
+        text: String,
+    },
+    /// A snapshot of all counters and gauges
+    #[serde(rename = "COUNTERS")]
+    Counters {
+        /// Microseconds since the Unix epoch when the record was written
+        timestamp: u64,
+        /// Microseconds on the same timeline as ``Sample::us_start``
+        us_time: u64,
+        /// Values of all counters and gauges
+        counters: Vec<CounterValue>,
+    },
+    /// End of a frame marked with ``Remotery::mark_frame``
+    #[serde(rename = "FRAME")]
+    Frame {
+        /// Microseconds since the Unix epoch when the record was written
+        timestamp: u64,
+        /// Index of the frame that ended
+        frame_index: u32,
+        /// Start of the frame in microseconds on the same timeline as ``Sample::us_start``
+        us_start: u64,
+        /// Length of the frame in microseconds
+        us_length: u64,
+    },
+}
+
+impl Record {
+    /// Creates a ``SAMPLES`` record for ``tree`` stamped with the current time
+    pub fn from_sample_tree(tree: &SampleTree) -> Record {
+        let mut nb_samples = 0;
+        tree.root.visit(&mut |_| nb_samples += 1);
+
+        Record::Samples {
+            timestamp: timestamp(),
+            thread_name: tree.thread_name.clone(),
+            nb_samples,
+            frame_index: tree.frame_index,
+            samples: vec![tree.root.clone()],
+        }
+    }
+
+    /// Creates a ``COUNTERS`` record stamped with the current time
+    pub fn from_counters(us_time: u64, counters: Vec<CounterValue>) -> Record {
+        Record::Counters {
+            timestamp: timestamp(),
+            us_time,
+            counters,
+        }
+    }
+
+    /// Creates a ``FRAME`` record stamped with the current time
+    pub fn from_frame(frame_index: u32, us_start: u64, us_length: u64) -> Record {
+        Record::Frame {
+            timestamp: timestamp(),
+            frame_index,
+            us_start,
+            us_length,
+        }
+    }
+
+    ///
+    /// Creates a record for a message received by ``client::Client`` stamped with the current
+    /// time. ``LOG`` messages don't say which thread they came from so the thread name is left
+    /// empty. Returns ``None`` for messages that aren't recorded (such as ``PING``).
+    ///
+    pub fn from_message(message: &Message) -> Option<Record> {
+        match *message {
+            Message::Samples { ref thread_name, nb_samples, frame_index, ref samples, .. } => {
+                Some(Record::Samples {
+                    timestamp: timestamp(),
+                    thread_name: thread_name.clone(),
+                    nb_samples,
+                    frame_index,
+                    samples: samples.clone(),
+                })
+            }
+            Message::Log { ref text } => {
+                Some(Record::Log {
+                    timestamp: timestamp(),
+                    thread_name: String::new(),
+                    text: text.clone(),
+                })
+            }
+            Message::Counters { us_time, ref counters } => Some(Record::from_counters(us_time, counters.clone())),
+            Message::Frame { frame_index, us_start, us_length } => Some(Record::from_frame(frame_index, us_start, us_length)),
+            Message::Ping | Message::Unknown => None,
+        }
+    }
+
+    /// Returns the sample trees of a ``SAMPLES`` record
+    pub fn sample_trees(&self) -> Vec<SampleTree> {
+        match *self {
+            Record::Samples { ref thread_name, ref samples, frame_index, .. } => {
+                samples.iter().map(|root| SampleTree {
+                    thread_name: thread_name.clone(),
+                    root: root.clone(),
+                    frame_index,
+                }).collect()
+            }
+            Record::Log { .. } | Record::Counters { .. } | Record::Frame { .. } => Vec::new(),
+        }
+    }
+}
+
+fn timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros() as u64).unwrap_or(0)
+}
+
+/// Iterator over the records of a capture, see ``read``
+pub struct Records<R: Read> {
+    inner: serde_json::StreamDeserializer<'static, serde_json::de::IoRead<R>, Record>,
+}
+
+impl<R: Read> Iterator for Records<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<io::Result<Record>> {
+        self.inner.next().map(|record| record.map_err(io::Error::from))
+    }
+}
+
+/// Reads the records of a capture one at a time
+pub fn read<R: Read>(reader: R) -> Records<R> {
+    Records {
+        inner: serde_json::Deserializer::from_reader(reader).into_iter(),
+    }
+}
+
+/// Writes records as lines of a capture
+pub struct Writer<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> Writer<W> {
+    /// Writes records to ``inner``, which should be buffered
+    pub fn new(inner: W) -> Writer<W> {
+        Writer { inner }
+    }
+
+    /// Appends ``record`` as a line
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        serde_json::to_writer(&mut self.inner, record)?;
+        self.inner.write_all(b"\n")
+    }
+
+    /// Flushes the underlying writer
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Returns the underlying writer
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+// How often the capture file is flushed, so it's mostly complete even if the program never
+// shuts down Remotery
+#[cfg(feature = "enabled")]
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[cfg(feature = "enabled")]
+struct BufferedWriter {
+    writer: Writer<BufWriter<File>>,
+    last_flush: Instant,
+}
+
+/// Writes records to a capture file, shared between the sample tree and log handlers
+#[cfg(feature = "enabled")]
+pub(crate) struct CaptureFile {
+    buffered: Mutex<BufferedWriter>,
+}
+
+#[cfg(feature = "enabled")]
+impl CaptureFile {
+    pub fn create(path: &Path) -> Result<CaptureFile, RemoteryError> {
+        let file = File::create(path).map_err(|_| RemoteryError::CaptureFileFail)?;
+
+        Ok(CaptureFile {
+            buffered: Mutex::new(BufferedWriter {
+                writer: Writer::new(BufWriter::new(file)),
+                last_flush: Instant::now(),
+            }),
+        })
+    }
+
+    ///
+    /// Appends ``record`` as a line, flushing the file if it hasn't been for ``FLUSH_INTERVAL``.
+    /// Write errors are ignored as there is nobody to report them to on the Remotery thread.
+    ///
+    pub fn write(&self, record: &Record) {
+        let mut buffered = self.buffered.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buffered.writer.write(record).is_ok() && buffered.last_flush.elapsed() >= FLUSH_INTERVAL {
+            let _ = buffered.writer.flush();
+            buffered.last_flush = Instant::now();
+        }
+    }
+}
+
+#[cfg(feature = "enabled")]
+impl Drop for CaptureFile {
+    fn drop(&mut self) {
+        let buffered = self.buffered.get_mut().unwrap_or_else(|e| e.into_inner());
+        let _ = buffered.writer.flush();
+    }
+}
+
+// The text field of the LOG message sent to the viewer
+#[cfg(feature = "enabled")]
+#[derive(Deserialize)]
+struct LogMessage {
+    text: String,
+}
+
+///
+/// Called by the Remotery thread for each logged line with the JSON ``LOG`` message that the
+/// viewer would get.
+///
+#[cfg(feature = "enabled")]
+pub(crate) unsafe extern "C" fn log_callback(context: *mut c_void, thread_name: *const c_char, message: *const c_char, length: c_uint) {
+    if context.is_null() || message.is_null() {
+        return;
+    }
+
+    let capture = &*(context as *const CaptureFile);
+    let message = slice::from_raw_parts(message as *const u8, length as usize);
+
+    let thread_name = if thread_name.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(thread_name).to_string_lossy().into_owned()
+    };
+
+    if let Ok(log) = serde_json::from_slice::<LogMessage>(message) {
+        capture.write(&Record::Log {
+            timestamp: timestamp(),
+            thread_name,
+            text: log.text,
+        });
+    }
+}
+
+///
+/// Called by the Remotery thread for each frame ended with ``Remotery::mark_frame``.
+///
+#[cfg(feature = "enabled")]
+pub(crate) unsafe extern "C" fn frame_callback(context: *mut c_void, frame_index: c_uint, us_start: u64, us_length: u64) {
+    if context.is_null() {
+        return;
+    }
+
+    let capture = &*(context as *const CaptureFile);
+    capture.write(&Record::from_frame(frame_index, us_start, us_length));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use counters::Value;
+
+    fn tree() -> SampleTree {
+        let child = Sample { name: "update".to_owned(), name_hash: 2, id: 20, us_start: 12, us_length: 30, children: vec![] };
+
+        SampleTree {
+            thread_name: "main".to_owned(),
+            root: Sample { name: "frame".to_owned(), name_hash: 1, id: 10, us_start: 10, us_length: 50, children: vec![child] },
+            frame_index: 4,
+        }
+    }
+
+    #[test]
+    fn test_records_round_trip() {
+        let records = vec![
+            Record::from_sample_tree(&tree()),
+            Record::Log { timestamp: 7, thread_name: "main".to_owned(), text: "say \"hi\"".to_owned() },
+            Record::from_counters(60, vec![CounterValue { name: "entities".to_owned(), value: Value::Counter(3) }]),
+            Record::from_frame(4, 10, 55),
+        ];
+
+        let mut writer = Writer::new(Vec::new());
+
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+
+        let data = writer.into_inner();
+
+        let text = String::from_utf8(data.clone()).unwrap();
+        assert!(text.starts_with("{\"id\":\"SAMPLES\",\"timestamp\":"));
+        assert!(text.contains("\"nb_samples\":2,\"frame_index\":4"));
+        assert!(text.lines().nth(1).unwrap().starts_with("{\"id\":\"LOG\""));
+        assert!(text.lines().nth(2).unwrap().ends_with("\"us_time\":60,\"counters\":[{\"name\":\"entities\",\"value\":3}]}"));
+        assert!(text.lines().nth(3).unwrap().ends_with("\"frame_index\":4,\"us_start\":10,\"us_length\":55}"));
+
+        let read: Vec<Record> = read(&data[..]).map(|r| r.unwrap()).collect();
+        assert_eq!(read, records);
+        assert_eq!(read[0].sample_trees(), vec![tree()]);
+    }
+
+    #[test]
+    fn test_read_viewer_message() {
+        // Sample trees as sent to the viewer have colours but no name hashes
+        let message = br##"{"id":"SAMPLES","timestamp":1,"thread_name":"main","nb_samples":1,"sample_digest":5,"samples":[{"name":"frame","id":10,"colour":"#ff0000","us_start":1,"us_length":2,"children":[]}]}"##;
+        let record = read(&message[..]).next().unwrap().unwrap();
+
+        assert_eq!(record.sample_trees()[0].root.name, "frame");
+    }
+
+    #[test]
+    #[cfg(feature = "enabled")]
+    fn test_log_callback() {
+        let path = ::std::env::temp_dir().join(format!("remotery_capture_test_{}.jsonl", ::std::process::id()));
+        let capture = CaptureFile::create(&path).unwrap();
+        let message = br#"{ "id": "LOG", "text": "loaded \"map\"" }"#;
+
+        unsafe {
+            log_callback(&capture as *const CaptureFile as *mut c_void, b"worker\0".as_ptr() as *const c_char,
+                         message.as_ptr() as *const c_char, message.len() as c_uint);
+        }
+
+        drop(capture);
+
+        let records: Vec<Record> = read(File::open(&path).unwrap()).map(|r| r.unwrap()).collect();
+        let _ = ::std::fs::remove_file(&path);
+
+        match records[..] {
+            [Record::Log { ref thread_name, ref text, .. }] => {
+                assert_eq!(thread_name, "worker");
+                assert_eq!(text, "loaded \"map\"");
+            }
+            _ => panic!("unexpected records {:?}", records),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "enabled")]
+    fn test_frame_callback() {
+        let path = ::std::env::temp_dir().join(format!("remotery_capture_frame_test_{}.jsonl", ::std::process::id()));
+        let capture = CaptureFile::create(&path).unwrap();
+
+        unsafe {
+            frame_callback(&capture as *const CaptureFile as *mut c_void, 41, 1_000, 16_667);
+        }
+
+        drop(capture);
+
+        let records: Vec<Record> = read(File::open(&path).unwrap()).map(|r| r.unwrap()).collect();
+        let _ = ::std::fs::remove_file(&path);
+
+        match records[..] {
+            [Record::Frame { frame_index: 41, us_start: 1_000, us_length: 16_667, .. }] => (),
+            _ => panic!("unexpected records {:?}", records),
+        }
+    }
+}
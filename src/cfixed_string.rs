@@ -1,7 +1,6 @@
 use std::borrow::{Borrow, Cow};
 use std::ffi::{CStr, CString};
 use std::fmt;
-use std::mem;
 use std::ops;
 use std::os::raw::c_char;
 use std::ptr;
@@ -14,6 +13,7 @@ const STRING_SIZE: usize = 512;
 /// generous bounds (512 bytes) of the statically sized buffer.
 /// Strings over this limit will be heap allocated, but the
 /// interface outside of this abstraction remains the same.
+#[allow(clippy::large_enum_variant)]
 pub enum CFixedString {
     Local{ s: [c_char; STRING_SIZE], len: usize },
     Heap{ s: CString, len: usize },
@@ -23,11 +23,9 @@ impl CFixedString {
     /// Creates an empty CFixedString, this is intended to be
     /// used with write! or the `fmt::Write` trait
     pub fn new() -> Self {
-        unsafe {
-            CFixedString::Local {
-                s: mem::uninitialized(),
-                len: 0,
-            }
+        CFixedString::Local {
+            s: [0; STRING_SIZE],
+            len: 0,
         }
     }
 
@@ -44,10 +42,7 @@ impl CFixedString {
 
     /// Returns true if the string has been heap allocated
     pub fn is_allocated(&self) -> bool {
-        match *self {
-            CFixedString::Local{..} => false,
-            _ => true,
-        }
+        !matches!(*self, CFixedString::Local{..})
     }
 
     /// Converts a `CFixedString` into a `Cow<str>`.
@@ -57,8 +52,8 @@ impl CFixedString {
     /// resulting slice as a `Cow<str>`, replacing any invalid UTF-8 sequences
     /// with `U+FFFD REPLACEMENT CHARACTER`. If there are no invalid UTF-8
     /// sequences, this will merely return a borrowed slice.
-    pub fn to_string(&self) -> Cow<str> {
-        String::from_utf8_lossy(&self.to_bytes())
+    pub fn to_string(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.to_bytes())
     }
 
     pub unsafe fn as_str(&self) -> &str {
@@ -92,12 +87,12 @@ impl fmt::Write for CFixedString {
             let cur_len = self.as_str().len();
 
             match cur_len + s.len() {
-                len if len <= STRING_SIZE - 1 => {
+                len if len < STRING_SIZE => {
                     match *self {
                         CFixedString::Local{ s: ref mut ls, len: ref mut lslen } => {
                             let ptr = ls.as_mut_ptr() as *mut u8;
-                            ptr::copy(s.as_ptr(), ptr.offset(cur_len as isize), s.len());
-                            *ptr.offset(len as isize) = 0;
+                            ptr::copy(s.as_ptr(), ptr.add(cur_len), s.len());
+                            *ptr.add(len) = 0;
                             *lslen = len;
                         },
                         _ => unreachable!(),
@@ -111,7 +106,7 @@ impl fmt::Write for CFixedString {
 
                     *self = CFixedString::Heap {
                         s: CString::new(heapstring).unwrap(),
-                        len: len,
+                        len,
                     };
                 }
             }
@@ -124,7 +119,7 @@ impl fmt::Write for CFixedString {
 
 impl From<CFixedString> for String {
     fn from(s: CFixedString) -> Self {
-        String::from_utf8_lossy(&s.to_bytes()).into_owned()
+        String::from_utf8_lossy(s.to_bytes()).into_owned()
     }
 }
 
@@ -136,7 +131,7 @@ impl ops::Deref for CFixedString {
 
         match *self {
             CFixedString::Local{ref s, len} => unsafe {
-                mem::transmute(slice::from_raw_parts(s.as_ptr(), len + 1))
+                CStr::from_bytes_with_nul_unchecked(slice::from_raw_parts(s.as_ptr() as *const u8, len + 1))
             },
             CFixedString::Heap{ref s, ..} => s,
         }
@@ -159,6 +154,7 @@ impl AsRef<str> for CFixedString {
     fn as_ref(&self) -> &str { unsafe { self.as_str() } }
 }
 
+#[cfg(test)]
 macro_rules! format_c {
     // This does not work on stable, to change the * to a + and
     // have this arm be used when there are no arguments :(
@@ -192,7 +188,7 @@ mod tests {
         }
 
         for i in 0..len % 16 {
-            out.write_char((i as u8 + 'A' as u8) as char).unwrap();
+            out.write_char((i as u8 + b'A') as char).unwrap();
         }
 
         assert_eq!(out.len(), len);
@@ -280,6 +276,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::write_literal)]
     fn test_short_format() {
         let mut fixed = CFixedString::new();
 
@@ -326,7 +323,7 @@ mod tests {
     fn test_short_fmt_macro() {
         let first = 23;
         let second = "#@!*()&^%_-+={}[]|\\/?><,.:;~`";
-        let third = u32::max_value();
+        let third = u32::MAX;
         let fourth = gen_string(512 - 45);
 
         let fixed = format_c!("{}_{}_0x{:x}_{}", first, second, third, fourth);
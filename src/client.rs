@@ -0,0 +1,524 @@
+//! Client for the protocol spoken between a Remotery server and the viewer. It connects to a
+//! running instance over WebSocket and decodes the ``SAMPLES``, ``LOG``, ``COUNTERS``, ``FRAME``
+//! and ``PING`` messages, which lets tools and integration tests read live profiling data without
+//! a browser.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let mut client = Client::connect("127.0.0.1:17815")?;
+//! client.send_console_input("reload shaders")?;
+//!
+//! loop {
+//!     match client.receive()? {
+//!         Message::Samples { .. } => ...,
+//!         Message::Log { text } => println!("{}", text),
+//!         _ => (),
+//!     }
+//! }
+//! ```
+//!
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(windows)]
+use std::os::windows::io::{AsRawSocket, RawSocket};
+use std::time::Duration;
+use serde_json;
+use counters::CounterValue;
+use error::RemoteryError;
+use sample_tree::{Sample, SampleTree};
+
+// Appended to the key of the client to calculate the accept key of the server
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+
+// Largest message accepted from the server, far more than Remotery's message queues can hold
+const MAX_MESSAGE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// A message sent by the Remotery server
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "id")]
+pub enum Message {
+    /// A completed sample tree of one thread
+    #[serde(rename = "SAMPLES")]
+    Samples {
+        /// Name of the thread the samples were taken on
+        thread_name: String,
+        /// Number of samples in the tree
+        nb_samples: u32,
+        /// Hash of the ids of all samples in the tree
+        sample_digest: u32,
+        /// Frame the tree belongs to, see ``Remotery::mark_frame``
+        #[serde(default)]
+        frame_index: u32,
+        /// The outermost sample
+        samples: Vec<Sample>,
+    },
+    /// A line sent with ``Remotery::log_text``
+    #[serde(rename = "LOG")]
+    Log {
+        /// The logged text
+        text: String,
+    },
+    /// Snapshot of all counters and gauges
+    #[serde(rename = "COUNTERS")]
+    Counters {
+        /// Microseconds on the same timeline as ``Sample::us_start``
+        us_time: u64,
+        /// Values of all counters and gauges
+        counters: Vec<CounterValue>,
+    },
+    /// End of a frame marked with ``Remotery::mark_frame``
+    #[serde(rename = "FRAME")]
+    Frame {
+        /// Index of the frame that ended, the same as ``SampleTree::frame_index`` of its trees
+        frame_index: u32,
+        /// Start of the frame in microseconds on the same timeline as ``Sample::us_start``
+        us_start: u64,
+        /// Length of the frame in microseconds
+        us_length: u64,
+    },
+    /// Sent by the server about once a second to check that the connection is alive
+    #[serde(rename = "PING")]
+    Ping,
+    /// A message with an id this client doesn't know about
+    #[serde(other)]
+    Unknown,
+}
+
+impl Message {
+    /// Parses a message as sent by the server
+    pub fn parse(data: &[u8]) -> io::Result<Message> {
+        serde_json::from_slice(&escape_control_bytes(data)).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Returns the sample trees of a ``SAMPLES`` message
+    pub fn sample_trees(&self) -> Vec<SampleTree> {
+        match *self {
+            Message::Samples { ref thread_name, ref samples, frame_index, .. } => {
+                samples.iter().map(|root| SampleTree {
+                    thread_name: thread_name.clone(),
+                    root: root.clone(),
+                    frame_index,
+                }).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A connection to a Remotery server
+pub struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    peer_addr: SocketAddr,
+}
+
+impl Client {
+    ///
+    /// Connects to the server at ``addr`` (such as ``127.0.0.1:17815``) and performs the
+    /// WebSocket handshake. Each connection takes up one of the viewer slots of the server, see
+    /// ``RemoterySettings::max_clients``.
+    ///
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Client> {
+        Self::connect_with(addr, None)
+    }
+
+    ///
+    /// Same as ``connect`` for servers that require an access token (see
+    /// ``RemoterySettings::access_token``). A wrong token is reported as an error of kind
+    /// ``PermissionDenied``.
+    ///
+    pub fn connect_with_token<A: ToSocketAddrs>(addr: A, token: &str) -> io::Result<Client> {
+        Self::connect_with(addr, Some(token))
+    }
+
+    fn connect_with<A: ToSocketAddrs>(addr: A, token: Option<&str>) -> io::Result<Client> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+
+        let peer_addr = stream.peer_addr()?;
+        let mut client = Client {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            peer_addr,
+        };
+
+        client.handshake(token)?;
+        Ok(client)
+    }
+
+    /// Address of the server
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    ///
+    /// Sets how long ``receive`` waits for a message. ``None`` (the default) waits forever.
+    /// A timeout is reported as an error of kind ``WouldBlock`` or ``TimedOut`` depending on the
+    /// platform.
+    ///
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.writer.set_read_timeout(timeout)
+    }
+
+    ///
+    /// Waits for the next message from the server. Messages that can't be parsed are reported as
+    /// errors of kind ``InvalidData`` after which the client can still be used. Messages larger
+    /// than 64 MB are also reported as ``InvalidData`` but leave the connection unusable.
+    ///
+    pub fn receive(&mut self) -> io::Result<Message> {
+        let payload = self.receive_frame()?;
+        Message::parse(&payload)
+    }
+
+    /// Sends ``text`` as console input (``CONI``), like typing it into the console of the viewer
+    pub fn send_console_input(&mut self, text: &str) -> io::Result<()> {
+        let mut payload = b"CONI".to_vec();
+        payload.extend_from_slice(text.as_bytes());
+        self.send_frame(OPCODE_TEXT, &payload)
+    }
+
+    fn handshake(&mut self, token: Option<&str>) -> io::Result<()> {
+        let key = base64(&random_bytes());
+        let authorization = token.map_or(String::new(), |token| format!("Authorization: Bearer {}\r\n", token));
+
+        let request = format!("GET / HTTP/1.1\r\n\
+                               Host: {}\r\n\
+                               Upgrade: websocket\r\n\
+                               Connection: Upgrade\r\n\
+                               Sec-WebSocket-Key: {}\r\n\
+                               Sec-WebSocket-Version: 13\r\n\
+                               {}\r\n", self.peer_addr, key, authorization);
+
+        self.writer.write_all(request.as_bytes())?;
+
+        let mut status = String::new();
+        self.reader.read_line(&mut status)?;
+
+        if status.starts_with("HTTP/1.1 403") {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, RemoteryError::WebsocketHandshakeBadToken));
+        }
+
+        if !status.starts_with("HTTP/1.1 101") {
+            return Err(handshake_error(&format!("unexpected response \"{}\"", status.trim())));
+        }
+
+        let mut accept = None;
+
+        loop {
+            let mut line = String::new();
+
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(handshake_error("connection closed during handshake"));
+            }
+
+            let line = line.trim();
+
+            if line.is_empty() {
+                break;
+            }
+
+            if let Some(colon) = line.find(':') {
+                if line[..colon].eq_ignore_ascii_case("Sec-WebSocket-Accept") {
+                    accept = Some(line[colon + 1..].trim().to_owned());
+                }
+            }
+        }
+
+        let expected = base64(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()));
+
+        if accept.as_deref() != Some(expected.as_str()) {
+            return Err(handshake_error("bad Sec-WebSocket-Accept key"));
+        }
+
+        Ok(())
+    }
+
+    fn receive_frame(&mut self) -> io::Result<Vec<u8>> {
+        let mut payload = Vec::new();
+
+        loop {
+            let mut header = [0u8; 2];
+            self.reader.read_exact(&mut header)?;
+
+            let fin = header[0] & 0x80 != 0;
+            let opcode = header[0] & 0x0F;
+            let masked = header[1] & 0x80 != 0;
+
+            let length = match header[1] & 0x7F {
+                126 => {
+                    let mut size = [0u8; 2];
+                    self.reader.read_exact(&mut size)?;
+                    u16::from_be_bytes(size) as u64
+                }
+                127 => {
+                    let mut size = [0u8; 8];
+                    self.reader.read_exact(&mut size)?;
+                    u64::from_be_bytes(size)
+                }
+                length => length as u64,
+            };
+
+            let mut mask = [0u8; 4];
+
+            if masked {
+                self.reader.read_exact(&mut mask)?;
+            }
+
+            // The length comes straight from the socket so it's checked before allocating
+            if length > MAX_MESSAGE_SIZE - payload.len() as u64 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Remotery message too large"));
+            }
+
+            let start = payload.len();
+            payload.resize(start + length as usize, 0);
+            self.reader.read_exact(&mut payload[start..])?;
+
+            if masked {
+                for (i, byte) in payload[start..].iter_mut().enumerate() {
+                    *byte ^= mask[i & 3];
+                }
+            }
+
+            match opcode {
+                OPCODE_CLOSE => {
+                    return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "Remotery server closed the connection"));
+                }
+                OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                    if fin {
+                        return Ok(payload);
+                    }
+                }
+                // Control frames (ping/pong) aren't sent by Remotery so just skip them
+                _ => payload.truncate(start),
+            }
+        }
+    }
+
+    // Frames sent by a client must be masked
+    fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let mut frame = vec![0x80 | opcode];
+
+        if payload.len() <= 125 {
+            frame.push(0x80 | payload.len() as u8);
+        } else if payload.len() <= 0xFFFF {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        } else {
+            frame.push(0x80 | 127);
+            frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+        }
+
+        let mask = random_bytes();
+        frame.extend_from_slice(&mask[..4]);
+        frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i & 3]));
+
+        self.writer.write_all(&frame)
+    }
+}
+
+/// The socket of the connection, such as for shutting it down from a signal handler
+#[cfg(unix)]
+impl AsRawFd for Client {
+    fn as_raw_fd(&self) -> RawFd {
+        self.writer.as_raw_fd()
+    }
+}
+
+/// The socket of the connection, such as for shutting it down from a console control handler
+#[cfg(windows)]
+impl AsRawSocket for Client {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.writer.as_raw_socket()
+    }
+}
+
+///
+/// The server doesn't escape control characters in names and log text, which isn't valid JSON,
+/// so the ones inside strings are escaped here to keep the text as it was sent.
+///
+fn escape_control_bytes(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    let (mut in_string, mut after_backslash) = (false, false);
+
+    for &c in data {
+        if in_string && c < 0x20 {
+            escaped.extend_from_slice(format!("\\u{:04x}", c).as_bytes());
+        } else {
+            escaped.push(c);
+        }
+
+        if after_backslash {
+            after_backslash = false;
+        } else if in_string && c == b'\\' {
+            after_backslash = true;
+        } else if c == b'"' {
+            in_string = !in_string;
+        }
+    }
+
+    escaped
+}
+
+fn handshake_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Remotery WebSocket handshake failed: {}", message))
+}
+
+// Randomly seeded so good enough for keys and masks, which only need to be unpredictable
+fn random_bytes() -> [u8; 16] {
+    let state = RandomState::new();
+    let mut bytes = [0u8; 16];
+
+    for (i, chunk) in bytes.chunks_mut(8).enumerate() {
+        let mut hasher = state.build_hasher();
+        hasher.write_usize(i);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+
+    bytes
+}
+
+fn base64(data: &[u8]) -> String {
+    const CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(CHARS[(bits >> (18 - i * 6)) as usize & 0x3F] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+
+    text
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([block[i * 4], block[i * 4 + 1], block[i * 4 + 2], block[i * 4 + 3]]);
+        }
+
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+
+    let mut digest = [0u8; 20];
+
+    for (i, word) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use counters::Value;
+
+    #[test]
+    fn test_accept_key() {
+        // Example from RFC 6455
+        let key = "dGhlIHNhbXBsZSBub25jZQ==";
+        let accept = base64(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()));
+
+        assert_eq!(accept, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+
+    #[test]
+    fn test_parse_messages() {
+        assert_eq!(Message::parse(br#"{ "id": "PING" }"#).unwrap(), Message::Ping);
+        assert_eq!(Message::parse(b"{ \"id\": \"LOG\", \"text\": \"a\tb \\\"c\\\"\\\\\" }").unwrap(),
+                   Message::Log { text: "a\tb \"c\"\\".to_owned() });
+        assert_eq!(Message::parse(b"{\n\t\"id\": \"LOG\",\r\n\t\"text\": \"line\nbreak\x01\"\n}").unwrap(),
+                   Message::Log { text: "line\nbreak\u{1}".to_owned() });
+        assert_eq!(Message::parse(br#"{ "id": "NEW" }"#).unwrap(), Message::Unknown);
+        assert_eq!(Message::parse(br#"{"id":"COUNTERS","us_time":5,"counters":[{"name":"entities","value":3}]}"#).unwrap(),
+                   Message::Counters { us_time: 5, counters: vec![CounterValue { name: "entities".to_owned(), value: Value::Counter(3) }] });
+        assert_eq!(Message::parse(br#"{"id":"FRAME","frame_index":7,"us_start":100,"us_length":16}"#).unwrap(),
+                   Message::Frame { frame_index: 7, us_start: 100, us_length: 16 });
+        assert_eq!(Message::parse(b"{").unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let samples = br##"{"id":"SAMPLES","thread_name":"main","nb_samples":2,"sample_digest":7,"frame_index":3,"samples":[{"name":"frame","id":1,"colour":"#ff0000","us_start":5,"us_length":20,"children":[{"name":"update","id":2,"colour":"#00ff00","us_start":6,"us_length":10,"children":[]}]}]}"##;
+        let trees = Message::parse(samples).unwrap().sample_trees();
+
+        assert_eq!(trees.len(), 1);
+        assert_eq!(trees[0].thread_name, "main");
+        assert_eq!(trees[0].root.children[0].name, "update");
+        assert_eq!(trees[0].frame_index, 3);
+    }
+
+    #[test]
+    fn test_oversized_message_is_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+
+        let mut client = Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            peer_addr: stream.peer_addr().unwrap(),
+            writer: stream,
+        };
+
+        // A text frame claiming to be 2^62 bytes long
+        server.write_all(&[0x81, 127, 0x40, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+
+        assert_eq!(client.receive().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
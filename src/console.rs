@@ -0,0 +1,433 @@
+//! Typed console commands on top of the console input of the Remotery viewer.
+//!
+//! Commands are registered with a signature such as ``spawn <count:u32> [name:str]`` where
+//! arguments in ``<>`` are required and arguments in ``[]`` are optional. Each argument is checked
+//! against its type before the handler is called. Unknown commands and arguments that fails to parse
+//! are reported back to the viewer with ``Remotery::log_text`` and the built-in ``help`` command
+//! lists all registered commands.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let mut commands = CommandRegistry::new();
+//!
+//! commands.register("spawn <count:u32>", "Spawns a number of entities", |args| {
+//!     let count: u32 = args.get("count").unwrap();
+//!     spawn_entities(count);
+//! }).unwrap();
+//!
+//! commands.install(&mut remotery);
+//! ```
+//!
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use Remotery;
+
+/// Types that can be used for command arguments
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArgType {
+    /// ``true`` or ``false``
+    Bool,
+    /// 32-bit signed integer
+    I32,
+    /// 32-bit unsigned integer
+    U32,
+    /// 64-bit signed integer
+    I64,
+    /// 64-bit unsigned integer
+    U64,
+    /// 32-bit float
+    F32,
+    /// 64-bit float
+    F64,
+    /// A single word
+    Str,
+    /// The rest of the line, only allowed as the last argument
+    Text,
+}
+
+impl ArgType {
+    fn from_name(name: &str) -> Option<ArgType> {
+        match name {
+            "bool" => Some(ArgType::Bool),
+            "i32" => Some(ArgType::I32),
+            "u32" => Some(ArgType::U32),
+            "i64" => Some(ArgType::I64),
+            "u64" => Some(ArgType::U64),
+            "f32" => Some(ArgType::F32),
+            "f64" => Some(ArgType::F64),
+            "str" => Some(ArgType::Str),
+            "text" => Some(ArgType::Text),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ArgType::Bool => "bool",
+            ArgType::I32 => "i32",
+            ArgType::U32 => "u32",
+            ArgType::I64 => "i64",
+            ArgType::U64 => "u64",
+            ArgType::F32 => "f32",
+            ArgType::F64 => "f64",
+            ArgType::Str => "str",
+            ArgType::Text => "text",
+        }
+    }
+
+    fn accepts(self, value: &str) -> bool {
+        match self {
+            ArgType::Bool => value.parse::<bool>().is_ok(),
+            ArgType::I32 => value.parse::<i32>().is_ok(),
+            ArgType::U32 => value.parse::<u32>().is_ok(),
+            ArgType::I64 => value.parse::<i64>().is_ok(),
+            ArgType::U64 => value.parse::<u64>().is_ok(),
+            ArgType::F32 => value.parse::<f32>().is_ok(),
+            ArgType::F64 => value.parse::<f64>().is_ok(),
+            ArgType::Str | ArgType::Text => true,
+        }
+    }
+}
+
+/// Errors from registering or running console commands. The ``Display`` output is what gets
+/// written back to the viewer.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommandError {
+    /// The signature passed to ``CommandRegistry::register`` couldn't be parsed
+    InvalidSignature(String),
+    /// A command with the same name has already been registered
+    DuplicateCommand(String),
+    /// No command has been registered with this name
+    UnknownCommand(String),
+    /// A required argument was not given
+    MissingArgument { command: String, name: String, ty: ArgType },
+    /// An argument didn't parse as the type in the signature
+    InvalidArgument { command: String, name: String, ty: ArgType, value: String },
+    /// More arguments was given than the command takes
+    TooManyArguments { command: String, usage: String },
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CommandError::InvalidSignature(ref sig) => write!(f, "Invalid command signature '{}'", sig),
+            CommandError::DuplicateCommand(ref name) => write!(f, "Command '{}' is already registered", name),
+            CommandError::UnknownCommand(ref name) => write!(f, "Unknown command '{}', type 'help' to list all commands", name),
+            CommandError::MissingArgument { ref command, ref name, ty } =>
+                write!(f, "{}: missing argument <{}:{}>", command, name, ty.name()),
+            CommandError::InvalidArgument { ref command, ref name, ty, ref value } =>
+                write!(f, "{}: argument '{}' expects {}, got '{}'", command, name, ty.name(), value),
+            CommandError::TooManyArguments { ref command, ref usage } =>
+                write!(f, "{}: too many arguments, usage: {}", command, usage),
+        }
+    }
+}
+
+impl Error for CommandError {}
+
+#[derive(Debug)]
+struct Param {
+    name: String,
+    ty: ArgType,
+    optional: bool,
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.optional {
+            write!(f, "[{}:{}]", self.name, self.ty.name())
+        } else {
+            write!(f, "<{}:{}>", self.name, self.ty.name())
+        }
+    }
+}
+
+struct Command {
+    name: String,
+    params: Vec<Param>,
+    help: String,
+    handler: Box<dyn Fn(&Args) + Send + Sync>,
+}
+
+impl Command {
+    fn usage(&self) -> String {
+        let mut usage = self.name.clone();
+
+        for param in &self.params {
+            usage.push_str(&format!(" {}", param));
+        }
+
+        usage
+    }
+}
+
+/// Arguments passed to a command handler. All arguments have been checked against the types
+/// in the signature before the handler is called.
+#[derive(Debug, Default)]
+pub struct Args {
+    values: Vec<(String, String)>,
+}
+
+impl Args {
+    /// Raw text of an argument, ``None`` if it's an optional argument that wasn't given
+    pub fn str(&self, name: &str) -> Option<&str> {
+        self.values.iter().find(|v| v.0 == name).map(|v| v.1.as_str())
+    }
+
+    /// Parses an argument into ``T``, ``None`` if the argument wasn't given or doesn't parse as ``T``
+    pub fn get<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.str(name).and_then(|v| v.parse().ok())
+    }
+}
+
+/// A set of named console commands that can be installed as the console input handler
+#[derive(Default)]
+pub struct CommandRegistry {
+    commands: Vec<Command>,
+}
+
+fn parse_signature(signature: &str) -> Result<(String, Vec<Param>), CommandError> {
+    let invalid = || CommandError::InvalidSignature(signature.to_owned());
+    let mut parts = signature.split_whitespace();
+    let name = parts.next().ok_or_else(invalid)?;
+
+    if name.starts_with('<') || name.starts_with('[') {
+        return Err(invalid());
+    }
+
+    let mut params: Vec<Param> = Vec::new();
+
+    for part in parts {
+        let optional = match (part.chars().next(), part.chars().last()) {
+            (Some('<'), Some('>')) => false,
+            (Some('['), Some(']')) => true,
+            _ => return Err(invalid()),
+        };
+
+        let mut decl = part[1..part.len() - 1].splitn(2, ':');
+        let param_name = decl.next().unwrap_or("");
+        let ty = decl.next().and_then(ArgType::from_name).ok_or_else(invalid)?;
+
+        let follows_text = params.last().is_some_and(|p| p.ty == ArgType::Text);
+        let follows_optional = params.last().is_some_and(|p| p.optional);
+
+        if param_name.is_empty() || follows_text || (follows_optional && !optional) ||
+           params.iter().any(|p| p.name == param_name) {
+            return Err(invalid());
+        }
+
+        params.push(Param { name: param_name.to_owned(), ty, optional });
+    }
+
+    Ok((name.to_owned(), params))
+}
+
+impl CommandRegistry {
+    /// Creates a registry with only the built-in ``help`` command
+    pub fn new() -> CommandRegistry {
+        CommandRegistry::default()
+    }
+
+    ///
+    /// Registers a command. ``signature`` is the command name followed by its arguments such as
+    /// ``spawn <count:u32> [name:str]``. Supported types are ``bool``, ``i32``, ``u32``, ``i64``,
+    /// ``u64``, ``f32``, ``f64``, ``str`` (a single word) and ``text`` (the rest of the line, only
+    /// allowed last). Optional arguments must come after the required ones.
+    ///
+    pub fn register<F>(&mut self, signature: &str, help: &str, handler: F) -> Result<(), CommandError>
+        where F: Fn(&Args) + Send + Sync + 'static
+    {
+        let (name, params) = parse_signature(signature)?;
+
+        if name == "help" || self.find(&name).is_some() {
+            return Err(CommandError::DuplicateCommand(name));
+        }
+
+        self.commands.push(Command {
+            name,
+            params,
+            help: help.to_owned(),
+            handler: Box::new(handler),
+        });
+
+        Ok(())
+    }
+
+    fn find(&self, name: &str) -> Option<&Command> {
+        self.commands.iter().find(|c| c.name == name)
+    }
+
+    /// Lists all commands, or a single command if ``command`` is given
+    pub fn help(&self, command: Option<&str>) -> Result<String, CommandError> {
+        if let Some(name) = command {
+            let command = self.find(name).ok_or_else(|| CommandError::UnknownCommand(name.to_owned()))?;
+            return Ok(format!("{} - {}", command.usage(), command.help));
+        }
+
+        let mut text = String::from("Available commands:\nhelp [command:str] - Lists all commands or shows the usage of one");
+
+        for command in &self.commands {
+            text.push_str(&format!("\n{} - {}", command.usage(), command.help));
+        }
+
+        Ok(text)
+    }
+
+    /// Parses a line of console input and calls the matching command handler
+    pub fn execute(&self, line: &str) -> Result<(), CommandError> {
+        let line = line.trim();
+        let (name, mut rest) = match line.find(char::is_whitespace) {
+            Some(pos) => (&line[..pos], line[pos..].trim_start()),
+            None => (line, ""),
+        };
+
+        if name.is_empty() {
+            return Ok(());
+        }
+
+        if name == "help" {
+            let command = if rest.is_empty() { None } else { Some(rest) };
+            Remotery::log_text(&self.help(command)?);
+            return Ok(());
+        }
+
+        let command = self.find(name).ok_or_else(|| CommandError::UnknownCommand(name.to_owned()))?;
+        let mut args = Args::default();
+
+        for param in &command.params {
+            let value = if param.ty == ArgType::Text {
+                let text = rest;
+                rest = "";
+                text
+            } else {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                let word = &rest[..end];
+                rest = rest[end..].trim_start();
+                word
+            };
+
+            if value.is_empty() {
+                if param.optional {
+                    break;
+                }
+
+                return Err(CommandError::MissingArgument {
+                    command: command.name.clone(),
+                    name: param.name.clone(),
+                    ty: param.ty,
+                });
+            }
+
+            if !param.ty.accepts(value) {
+                return Err(CommandError::InvalidArgument {
+                    command: command.name.clone(),
+                    name: param.name.clone(),
+                    ty: param.ty,
+                    value: value.to_owned(),
+                });
+            }
+
+            args.values.push((param.name.clone(), value.to_owned()));
+        }
+
+        if !rest.is_empty() {
+            return Err(CommandError::TooManyArguments {
+                command: command.name.clone(),
+                usage: command.usage(),
+            });
+        }
+
+        (command.handler)(&args);
+        Ok(())
+    }
+
+    /// Same as ``execute`` but writes any error back to the viewer with ``Remotery::log_text``
+    pub fn dispatch(&self, line: &str) {
+        if let Err(e) = self.execute(line) {
+            Remotery::log_text(&e.to_string());
+        }
+    }
+
+    /// Installs the registry as the console input handler of ``remotery``
+    pub fn install(self, remotery: &mut Remotery) {
+        remotery.set_input_handler(move |text| self.dispatch(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_invalid_signatures() {
+        let mut commands = CommandRegistry::new();
+
+        assert!(commands.register("", "", |_| {}).is_err());
+        assert!(commands.register("spawn <count>", "", |_| {}).is_err());
+        assert!(commands.register("spawn <count:u8>", "", |_| {}).is_err());
+        assert!(commands.register("spawn [count:u32] <name:str>", "", |_| {}).is_err());
+        assert!(commands.register("say <msg:text> <count:u32>", "", |_| {}).is_err());
+        assert!(commands.register("spawn <a:u32> <a:u32>", "", |_| {}).is_err());
+        assert!(commands.register("help", "", |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_typed_arguments() {
+        let spawned = Arc::new(Mutex::new(Vec::new()));
+        let mut commands = CommandRegistry::new();
+
+        {
+            let spawned = spawned.clone();
+            commands.register("spawn <count:u32> [name:str]", "Spawns entities", move |args| {
+                let count: u32 = args.get("count").unwrap();
+                let name = args.str("name").unwrap_or("default").to_owned();
+                spawned.lock().unwrap().push((count, name));
+            }).unwrap();
+        }
+
+        assert!(commands.execute("spawn 10").is_ok());
+        assert!(commands.execute("  spawn   3 orc ").is_ok());
+        assert_eq!(*spawned.lock().unwrap(), vec![(10, "default".to_owned()), (3, "orc".to_owned())]);
+
+        assert_eq!(commands.execute("spawn lots"), Err(CommandError::InvalidArgument {
+            command: "spawn".to_owned(),
+            name: "count".to_owned(),
+            ty: ArgType::U32,
+            value: "lots".to_owned(),
+        }));
+
+        assert!(commands.execute("spawn").is_err());
+        assert!(commands.execute("spawn 1 orc extra").is_err());
+        assert_eq!(commands.execute("despawn 1"), Err(CommandError::UnknownCommand("despawn".to_owned())));
+        assert_eq!(spawned.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_text_argument() {
+        let said = Arc::new(Mutex::new(String::new()));
+        let mut commands = CommandRegistry::new();
+
+        {
+            let said = said.clone();
+            commands.register("say <msg:text>", "Says something", move |args| {
+                *said.lock().unwrap() = args.str("msg").unwrap().to_owned();
+            }).unwrap();
+        }
+
+        commands.execute("say hello there  world").unwrap();
+        assert_eq!(*said.lock().unwrap(), "hello there  world");
+    }
+
+    #[test]
+    fn test_help() {
+        let mut commands = CommandRegistry::new();
+        commands.register("spawn <count:u32>", "Spawns entities", |_| {}).unwrap();
+
+        assert!(commands.help(None).unwrap().contains("spawn <count:u32> - Spawns entities"));
+        assert_eq!(commands.help(Some("spawn")).unwrap(), "spawn <count:u32> - Spawns entities");
+        assert!(commands.help(Some("jump")).is_err());
+    }
+}
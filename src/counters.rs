@@ -0,0 +1,395 @@
+//! Counters and gauges that are shown on the same timeline as the samples, such as memory use,
+//! entity counts or queue depths. Values are updated lock-free from any thread and snapshots of
+//! all of them are sent to the viewers as a ``COUNTERS`` message at a fixed interval (see
+//! ``RemoterySettings::counter_interval``). The thread sending them is only started once the
+//! first counter or gauge is registered.
+//!
+//! ```text
+//! {"id":"COUNTERS","us_time":1234567,"counters":[{"name":"entities","value":123},{"name":"load","value":0.75}]}
+//! ```
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let entities = Remotery::counter("entities");
+//! let load = Remotery::gauge("load");
+//!
+//! entities.set(123);
+//! entities.add(5);
+//! load.set(0.75);
+//! ```
+//!
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+#[cfg(feature = "enabled")]
+use std::sync::Mutex;
+#[cfg(feature = "enabled")]
+use std::sync::Condvar;
+#[cfg(feature = "enabled")]
+use std::thread::{self, JoinHandle};
+#[cfg(feature = "enabled")]
+use std::time::Duration;
+#[cfg(feature = "enabled")]
+use serde_json;
+#[cfg(feature = "enabled")]
+use capture::{CaptureFile, Record};
+#[cfg(feature = "enabled")]
+use remotery_ffi;
+
+/// An integer value such as an entity count, see ``Remotery::counter``
+#[derive(Clone, Debug, Default)]
+pub struct Counter {
+    value: Arc<AtomicI64>,
+}
+
+impl Counter {
+    /// Sets the counter to ``value``
+    pub fn set(&self, value: i64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    /// Adds ``delta`` (which may be negative) to the counter
+    pub fn add(&self, delta: i64) {
+        self.value.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    /// Returns the current value
+    pub fn get(&self) -> i64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// A floating point value such as a load factor, see ``Remotery::gauge``
+#[derive(Clone, Debug, Default)]
+pub struct Gauge {
+    bits: Arc<AtomicU64>,
+}
+
+impl Gauge {
+    /// Sets the gauge to ``value``. NaN and infinite values can't be sent to the viewers so they
+    /// are ignored and the gauge keeps its previous value.
+    pub fn set(&self, value: f64) {
+        if value.is_finite() {
+            self.bits.store(value.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the current value
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.bits.load(Ordering::Relaxed))
+    }
+}
+
+/// Value of a counter or gauge in a snapshot
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    /// Value of a ``Counter``
+    Counter(i64),
+    /// Value of a ``Gauge``
+    Gauge(f64),
+}
+
+/// A named value in a snapshot
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CounterValue {
+    /// Name the counter or gauge was registered with
+    pub name: String,
+    /// Value at the time of the snapshot
+    pub value: Value,
+}
+
+#[cfg(feature = "enabled")]
+enum Slot {
+    Counter(Counter),
+    Gauge(Gauge),
+}
+
+// All counters and gauges in the order they were first registered
+#[cfg(feature = "enabled")]
+static REGISTRY: Mutex<Vec<(String, Slot)>> = Mutex::new(Vec::new());
+
+#[cfg(feature = "enabled")]
+fn kind_mismatch(name: &str, kind: &str) -> ! {
+    panic!("Remotery counter \"{}\" is already registered as a {}", name, kind)
+}
+
+///
+/// Returns the counter called ``name``, registering it the first time. Panics if ``name`` is
+/// already used by a gauge.
+///
+#[cfg(feature = "enabled")]
+pub(crate) fn counter(name: &str) -> Counter {
+    let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+
+    match registry.iter().find(|entry| entry.0 == name) {
+        Some(&(_, Slot::Counter(ref counter))) => return counter.clone(),
+        Some(&(_, Slot::Gauge(_))) => kind_mismatch(name, "gauge"),
+        None => (),
+    }
+
+    let counter = Counter::default();
+    registry.push((name.to_owned(), Slot::Counter(counter.clone())));
+    drop(registry);
+
+    start_thread();
+
+    counter
+}
+
+///
+/// Returns the gauge called ``name``, registering it the first time. Panics if ``name`` is
+/// already used by a counter.
+///
+#[cfg(feature = "enabled")]
+pub(crate) fn gauge(name: &str) -> Gauge {
+    let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+
+    match registry.iter().find(|entry| entry.0 == name) {
+        Some(&(_, Slot::Gauge(ref gauge))) => return gauge.clone(),
+        Some(&(_, Slot::Counter(_))) => kind_mismatch(name, "counter"),
+        None => (),
+    }
+
+    let gauge = Gauge::default();
+    registry.push((name.to_owned(), Slot::Gauge(gauge.clone())));
+    drop(registry);
+
+    start_thread();
+
+    gauge
+}
+
+/// Returns the current values of all registered counters and gauges
+#[cfg(feature = "enabled")]
+pub fn snapshot() -> Vec<CounterValue> {
+    let registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+
+    registry.iter().map(|(name, slot)| {
+        CounterValue {
+            name: name.clone(),
+            value: match slot {
+                Slot::Counter(counter) => Value::Counter(counter.get()),
+                Slot::Gauge(gauge) => Value::Gauge(gauge.get()),
+            },
+        }
+    }).collect()
+}
+
+// Nothing is registered without the ``enabled`` feature
+#[cfg(not(feature = "enabled"))]
+pub fn snapshot() -> Vec<CounterValue> {
+    Vec::new()
+}
+
+// The message sent to the viewers
+#[cfg(feature = "enabled")]
+#[derive(Serialize)]
+#[serde(tag = "id", rename = "COUNTERS")]
+struct CountersMessage<'a> {
+    us_time: u64,
+    counters: &'a [CounterValue],
+}
+
+#[cfg(feature = "enabled")]
+#[derive(Default)]
+struct State {
+    running: bool,
+    interval: Option<Duration>,
+    capture: Option<Arc<CaptureFile>>,
+    thread: Option<JoinHandle<()>>,
+    requested: bool,
+    stop: bool,
+}
+
+// Snapshot thread of the instance and what wakes it up early, there is only one instance at a time
+#[cfg(feature = "enabled")]
+static STATE: Mutex<State> = Mutex::new(State {
+    running: false,
+    interval: None,
+    capture: None,
+    thread: None,
+    requested: false,
+    stop: false,
+});
+#[cfg(feature = "enabled")]
+static WAKE: Condvar = Condvar::new();
+
+///
+/// Asks the snapshot thread to send the counters now instead of waiting for the next interval.
+/// Does nothing without a running instance.
+///
+#[cfg(feature = "enabled")]
+pub(crate) fn request_snapshot() {
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    state.requested = true;
+    WAKE.notify_one();
+}
+
+// Sends a snapshot to the viewers and the capture file, if there is anything to send
+#[cfg(feature = "enabled")]
+fn send_snapshot(capture: Option<&CaptureFile>) {
+    let counters = snapshot();
+
+    if counters.is_empty() {
+        return;
+    }
+
+    let us_time = unsafe { remotery_ffi::_rmt_GetTime() };
+
+    if let Ok(json) = serde_json::to_vec(&CountersMessage { us_time, counters: &counters }) {
+        unsafe { remotery_ffi::_rmt_SendRawMessage(json.as_ptr() as *const _, json.len() as u32) };
+    }
+
+    if let Some(capture) = capture {
+        capture.write(&Record::from_counters(us_time, counters));
+    }
+}
+
+// Starts the snapshot thread of the running instance unless it's already running
+#[cfg(feature = "enabled")]
+fn start_thread() {
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+
+    if !state.running || state.thread.is_some() {
+        return;
+    }
+
+    let interval = state.interval;
+    let capture = state.capture.clone();
+
+    let thread = thread::Builder::new().name("Remotery counters".to_owned()).spawn(move || {
+        let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+
+        loop {
+            state = match interval {
+                Some(interval) if !state.requested && !state.stop => {
+                    WAKE.wait_timeout(state, interval).unwrap_or_else(|e| e.into_inner()).0
+                }
+                None => WAKE.wait_while(state, |s| !s.requested && !s.stop).unwrap_or_else(|e| e.into_inner()),
+                _ => state,
+            };
+
+            if state.stop {
+                break;
+            }
+
+            state.requested = false;
+
+            // Values are read without holding the lock so counters never wait on the thread
+            drop(state);
+            send_snapshot(capture.as_deref());
+            state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+        }
+    });
+
+    state.thread = thread.ok();
+}
+
+/// Owned by the ``Remotery`` instance, sends snapshots from a thread until dropped
+#[cfg(feature = "enabled")]
+pub(crate) struct Snapshots {
+    _private: (),
+}
+
+#[cfg(feature = "enabled")]
+impl Snapshots {
+    ///
+    /// Sends a snapshot every ``interval`` and whenever ``request_snapshot`` is called, from a
+    /// thread that is started once there is a counter or gauge. With no interval snapshots are
+    /// only sent when requested.
+    ///
+    pub fn start(interval: Option<Duration>, capture: Option<Arc<CaptureFile>>) -> Snapshots {
+        *STATE.lock().unwrap_or_else(|e| e.into_inner()) = State {
+            running: true,
+            interval,
+            capture,
+            ..State::default()
+        };
+
+        // Counters registered before the instance was created don't start the thread themselves
+        if !REGISTRY.lock().unwrap_or_else(|e| e.into_inner()).is_empty() {
+            start_thread();
+        }
+
+        Snapshots { _private: () }
+    }
+}
+
+#[cfg(feature = "enabled")]
+impl Drop for Snapshots {
+    fn drop(&mut self) {
+        let thread = {
+            let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+            state.running = false;
+            state.stop = true;
+            state.capture = None;
+            state.thread.take()
+        };
+
+        WAKE.notify_all();
+
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    #[cfg(feature = "enabled")]
+    fn test_counters_and_gauges() {
+        let entities = counter("tests::entities");
+        entities.set(120);
+        counter("tests::entities").add(3);
+
+        let load = gauge("tests::load");
+        load.set(0.75);
+
+        let values = snapshot();
+        let value = |name: &str| values.iter().find(|v| v.name == name).map(|v| v.value);
+
+        assert_eq!(value("tests::entities"), Some(Value::Counter(123)));
+        assert_eq!(value("tests::load"), Some(Value::Gauge(0.75)));
+    }
+
+    #[test]
+    #[cfg(feature = "enabled")]
+    fn test_gauge_ignores_non_finite_values() {
+        let gauge = gauge("tests::non_finite");
+        gauge.set(0.5);
+        gauge.set(f64::NAN);
+        gauge.set(f64::INFINITY);
+        gauge.set(f64::NEG_INFINITY);
+
+        assert_eq!(gauge.get(), 0.5);
+
+        let values = snapshot();
+        let json = serde_json::to_string(values.iter().find(|v| v.name == "tests::non_finite").unwrap()).unwrap();
+        assert_eq!(json, r#"{"name":"tests::non_finite","value":0.5}"#);
+    }
+
+    #[test]
+    #[cfg(feature = "enabled")]
+    #[should_panic(expected = "already registered as a counter")]
+    fn test_kind_mismatch() {
+        counter("tests::mismatch");
+        gauge("tests::mismatch");
+    }
+
+    #[test]
+    fn test_values_round_trip() {
+        let values = vec![
+            CounterValue { name: "a".to_owned(), value: Value::Counter(-5) },
+            CounterValue { name: "b".to_owned(), value: Value::Gauge(2.0) },
+        ];
+
+        let json = serde_json::to_string(&values).unwrap();
+        assert_eq!(json, r#"[{"name":"a","value":-5},{"name":"b","value":2.0}]"#);
+        assert_eq!(serde_json::from_str::<Vec<CounterValue>>(&json).unwrap(), values);
+    }
+}
@@ -0,0 +1,103 @@
+//
+// No-op versions of the public API used when the ``enabled`` feature is turned off. None of the C
+// code is compiled in this case so everything here compiles down to nothing while keeping the
+// same signatures as the real implementation.
+//
+use std::ffi::CStr;
+use std::marker::PhantomData;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::ptr;
+use std::sync::atomic::AtomicU32;
+use std::sync::mpsc::{self, Receiver};
+use counters::{Counter, Gauge};
+use error::RemoteryError;
+use frames::FrameStats;
+use sample_tree::SampleTree;
+use {Remotery, RemoteryScope, RemoterySettings, SampleFlags};
+
+impl Remotery {
+    #[inline(always)]
+    pub fn create_global_instance() -> Result<Remotery, RemoteryError> {
+        Self::create_global_instance_with(RemoterySettings::new())
+    }
+
+    #[inline(always)]
+    pub fn create_global_instance_with(settings: RemoterySettings) -> Result<Remotery, RemoteryError> {
+        settings.validate()?;
+        Ok(Remotery { instance: ptr::null_mut() })
+    }
+
+    #[inline(always)]
+    pub fn set_input_handler<F>(&mut self, _handler: F) where F: Fn(&str) + Send + Sync + 'static {}
+
+    #[inline(always)]
+    pub fn add_sample_tree_handler<F>(&mut self, _handler: F) where F: Fn(&SampleTree) + Send + Sync + 'static {}
+
+    #[inline(always)]
+    pub fn sample_tree_channel(&mut self) -> Receiver<SampleTree> {
+        mpsc::channel().1
+    }
+
+    #[inline(always)]
+    pub fn counter(_name: &str) -> Counter {
+        Counter::default()
+    }
+
+    #[inline(always)]
+    pub fn gauge(_name: &str) -> Gauge {
+        Gauge::default()
+    }
+
+    #[inline(always)]
+    pub fn send_counters() {}
+
+    #[inline(always)]
+    pub fn mark_frame() {}
+
+    #[inline(always)]
+    pub fn frame_stats() -> FrameStats {
+        FrameStats::default()
+    }
+
+    // Nothing is listening so report the unspecified address
+    #[inline(always)]
+    pub fn local_addr(&self) -> SocketAddr {
+        SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0)
+    }
+
+    #[inline(always)]
+    pub fn begin_cpu_sample(_name: &str, _flags: SampleFlags) {}
+
+    #[inline(always)]
+    pub fn begin_cpu_sample_cached(_name: &'static CStr, _flags: SampleFlags, _hash_cache: &AtomicU32) {}
+
+    #[cfg(feature = "tracing")]
+    #[inline(always)]
+    pub(crate) fn begin_cpu_sample_cstr(_name: &CStr, _flags: SampleFlags, _hash_cache: &AtomicU32) {}
+
+    #[inline(always)]
+    pub fn end_cpu_sample() {}
+
+    #[inline(always)]
+    pub fn set_current_thread_name(_name: &str) {}
+
+    #[inline(always)]
+    pub fn log_text(_text: &str) {}
+}
+
+impl RemoteryScope {
+    #[inline(always)]
+    pub fn new(_name: &str, _flags: SampleFlags) -> RemoteryScope {
+        RemoteryScope { _not_send: PhantomData }
+    }
+
+    #[inline(always)]
+    pub fn new_cached(_name: &'static CStr, _flags: SampleFlags, _hash_cache: &AtomicU32) -> RemoteryScope {
+        RemoteryScope { _not_send: PhantomData }
+    }
+
+    #[inline(always)]
+    pub(crate) fn new_cstr(_name: &CStr, _flags: SampleFlags, _hash_cache: &AtomicU32) -> RemoteryScope {
+        RemoteryScope { _not_send: PhantomData }
+    }
+}
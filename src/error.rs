@@ -1,5 +1,6 @@
 use std::fmt;
 use std::error::Error;
+use remotery_ffi::RmtError;
 
 #[derive(Debug, Clone, Copy)]
 pub enum RemoteryError {
@@ -89,6 +90,12 @@ pub enum RemoteryError {
     OpenglError = 42,
 	/// Unknown error
     Unknown = 43,
+	/// Settings passed to Remotery contained values that can't be used
+    InvalidSettings = 44,
+	/// The capture file given with ``CaptureMode::File`` couldn't be created
+    CaptureFileFail = 45,
+	/// WebSocket server handshake failed, missing or wrong access token
+    WebsocketHandshakeBadToken = 46,
 }
 
 fn remotery_error_to_string(id: RemoteryError) -> &'static str {
@@ -139,10 +146,18 @@ fn remotery_error_to_string(id: RemoteryError) -> &'static str {
         RemoteryError::D3d11FailedToCreateQuery => "Failed to create query for sample",
         RemoteryError::OpenglError => "Generic OpenGL error, no real need to expose more detail since app will probably have an OpenGL error callback registered",
         RemoteryError::Unknown => "Unknown error",
+        RemoteryError::InvalidSettings => "Settings passed to Remotery contained values that can't be used",
+        RemoteryError::CaptureFileFail => "The capture file couldn't be created",
+        RemoteryError::WebsocketHandshakeBadToken => "WebSocket server handshake failed, missing or wrong access token",
     }
 }
 
 pub fn get_error(id: u32) -> RemoteryError {
+    // Errors added to the C lib come after the ones only the Rust side has so they need mapping
+    if id == RmtError::RMT_ERROR_WEBSOCKET_HANDSHAKE_BAD_TOKEN as u32 {
+        return RemoteryError::WebsocketHandshakeBadToken;
+    }
+
     if id <= RemoteryError::Unknown as u32 {
         let error: RemoteryError = unsafe { ::std::mem::transmute(id as u8) };
         error
@@ -163,3 +178,20 @@ impl Error for RemoteryError  {
         remotery_error_to_string(*self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        // Existing codes keep their numbers when errors are added
+        assert_eq!(get_error(RmtError::RMT_ERROR_WEBSOCKET_DISCONNECTED as u32) as u32, RemoteryError::WebsocketDisconnected as u32);
+        assert_eq!(RemoteryError::Unknown as u32, 43);
+        assert_eq!(RemoteryError::InvalidSettings as u32, 44);
+        assert_eq!(get_error(RmtError::RMT_ERROR_CUDA_UNKNOWN as u32) as u32, RemoteryError::Unknown as u32);
+        assert_eq!(get_error(RmtError::RMT_ERROR_WEBSOCKET_HANDSHAKE_BAD_TOKEN as u32) as u32,
+                   RemoteryError::WebsocketHandshakeBadToken as u32);
+        assert_eq!(get_error(1000) as u32, RemoteryError::Unknown as u32);
+    }
+}
@@ -0,0 +1,189 @@
+//! Export to the [Chrome Trace Event Format](https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU)
+//! which can be loaded in ``chrome://tracing`` and [Perfetto](https://ui.perfetto.dev).
+//!
+//! Each sample becomes a complete event (``"ph": "X"``) with ``ts``/``dur`` taken from
+//! ``us_start``/``us_length`` and each thread gets a ``thread_name`` metadata event (``"ph": "M"``).
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let capture = File::open("profile.jsonl")?;
+//! let trace = BufWriter::new(File::create("profile.trace.json")?);
+//!
+//! remotery::export::chrome_trace::convert_capture(capture, trace)?;
+//! ```
+//!
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use serde_json;
+use capture;
+use sample_tree::{Sample, SampleTree};
+
+/// Process id used for all events as a capture only covers a single process
+const PID: u32 = 1;
+
+/// Arguments of a ``thread_name`` metadata event
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ThreadNameArgs {
+    /// Name of the thread
+    pub name: String,
+}
+
+/// A single trace event
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Event {
+    /// Name of the sample, or ``thread_name`` for metadata events
+    pub name: String,
+    /// Event type, ``X`` for complete events and ``M`` for metadata
+    pub ph: &'static str,
+    /// Start in microseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ts: Option<u64>,
+    /// Duration in microseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dur: Option<u64>,
+    /// Process id
+    pub pid: u32,
+    /// Thread id, assigned in the order threads are first seen
+    pub tid: u32,
+    /// Thread name of metadata events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub args: Option<ThreadNameArgs>,
+}
+
+/// Turns sample trees into trace events, keeping track of the thread ids handed out
+#[derive(Default)]
+pub struct Converter {
+    threads: HashMap<String, u32>,
+    events: Vec<Event>,
+}
+
+impl Converter {
+    /// Creates a converter without any events
+    pub fn new() -> Converter {
+        Converter::default()
+    }
+
+    /// Adds the events of all samples in ``tree``
+    pub fn add_tree(&mut self, tree: &SampleTree) {
+        let tid = self.thread_id(&tree.thread_name);
+        self.add_sample(&tree.root, tid);
+    }
+
+    /// Returns all events added so far
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Writes a trace file with all events added so far
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        let trace = Trace {
+            trace_events: &self.events,
+            display_time_unit: "ms",
+        };
+
+        serde_json::to_writer(writer, &trace).map_err(io::Error::from)
+    }
+
+    fn thread_id(&mut self, thread_name: &str) -> u32 {
+        if let Some(&tid) = self.threads.get(thread_name) {
+            return tid;
+        }
+
+        let tid = self.threads.len() as u32 + 1;
+        self.threads.insert(thread_name.to_owned(), tid);
+
+        self.events.push(Event {
+            name: "thread_name".to_owned(),
+            ph: "M",
+            ts: None,
+            dur: None,
+            pid: PID,
+            tid,
+            args: Some(ThreadNameArgs { name: thread_name.to_owned() }),
+        });
+
+        tid
+    }
+
+    fn add_sample(&mut self, sample: &Sample, tid: u32) {
+        self.events.push(Event {
+            name: sample.name.clone(),
+            ph: "X",
+            ts: Some(sample.us_start),
+            dur: Some(sample.us_length),
+            pid: PID,
+            tid,
+            args: None,
+        });
+
+        for child in &sample.children {
+            self.add_sample(child, tid);
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Trace<'a> {
+    #[serde(rename = "traceEvents")]
+    trace_events: &'a [Event],
+    #[serde(rename = "displayTimeUnit")]
+    display_time_unit: &'static str,
+}
+
+/// Writes a trace file with the events of all ``trees``
+pub fn write<W: Write>(writer: W, trees: &[SampleTree]) -> io::Result<()> {
+    let mut converter = Converter::new();
+
+    for tree in trees {
+        converter.add_tree(tree);
+    }
+
+    converter.write(writer)
+}
+
+/// Reads a capture written with ``CaptureMode::File`` and writes the sample trees in it as a trace
+pub fn convert_capture<R: Read, W: Write>(capture: R, writer: W) -> io::Result<()> {
+    let mut converter = Converter::new();
+
+    for record in capture::read(capture) {
+        for tree in record?.sample_trees() {
+            converter.add_tree(&tree);
+        }
+    }
+
+    converter.write(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(name: &str, us_start: u64, us_length: u64, children: Vec<Sample>) -> Sample {
+        Sample { name: name.to_owned(), name_hash: 0, id: 0, us_start, us_length, children }
+    }
+
+    #[test]
+    fn test_write_trace() {
+        let trees = vec![
+            SampleTree { thread_name: "main".to_owned(), root: sample("frame", 10, 50, vec![sample("update", 12, 20, vec![])]), frame_index: 0 },
+            SampleTree { thread_name: "worker".to_owned(), root: sample("job", 15, 5, vec![]), frame_index: 0 },
+            SampleTree { thread_name: "main".to_owned(), root: sample("frame", 60, 40, vec![]), frame_index: 0 },
+        ];
+
+        let mut data = Vec::new();
+        write(&mut data, &trees).unwrap();
+
+        let trace: serde_json::Value = serde_json::from_slice(&data).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+
+        assert_eq!(events.len(), 6);
+        assert_eq!(events[0], serde_json::json!({"name": "thread_name", "ph": "M", "pid": 1, "tid": 1, "args": {"name": "main"}}));
+        assert_eq!(events[1], serde_json::json!({"name": "frame", "ph": "X", "ts": 10, "dur": 50, "pid": 1, "tid": 1}));
+        assert_eq!(events[2], serde_json::json!({"name": "update", "ph": "X", "ts": 12, "dur": 20, "pid": 1, "tid": 1}));
+        assert_eq!(events[3]["args"]["name"], "worker");
+        assert_eq!(events[4]["tid"], 2);
+        assert_eq!(events[5]["ts"], 60);
+        assert_eq!(events[5]["tid"], 1);
+    }
+}
@@ -0,0 +1,4 @@
+//! Converters from captured sample trees to the file formats of other tools, so captures can be
+//! shared with people who never run the Remotery viewer.
+
+pub mod chrome_trace;
@@ -0,0 +1,243 @@
+//! Frame markers for programs that work in frames or ticks. ``Remotery::mark_frame`` (or dropping
+//! a ``FrameScope``) ends the current frame, which
+//!
+//! * stamps all sample trees completed after it with the next frame index (see
+//!   ``SampleTree::frame_index``)
+//! * sends a ``FRAME`` message with the length of the frame to the viewers and the capture file
+//! * adds the length to the history that ``Remotery::frame_stats`` is calculated over (see
+//!   ``RemoterySettings::frame_history``)
+//!
+//! The first frame starts when the instance is created.
+//!
+//! ```text
+//! {"id":"FRAME","frame_index":41,"us_start":1234567,"us_length":16667}
+//! ```
+//!
+//! # Examples
+//!
+//! ```ignore
+//! while running {
+//!     let _frame = FrameScope::new();
+//!     update();
+//!     render();
+//! }
+//!
+//! let stats = Remotery::frame_stats();
+//! println!("{:.1} fps, worst {} us, p99 {} us", stats.fps, stats.max_us, stats.p99_us);
+//! ```
+//!
+#[cfg(feature = "enabled")]
+use std::collections::VecDeque;
+#[cfg(feature = "enabled")]
+use std::sync::Mutex;
+#[cfg(feature = "enabled")]
+use remotery_ffi;
+use Remotery;
+
+/// Frame time statistics, see ``Remotery::frame_stats``. All times are in microseconds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameStats {
+    /// Number of frames the statistics were calculated over
+    pub frames: usize,
+    /// Frames per second going by the mean frame length
+    pub fps: f64,
+    /// Mean length of the frames
+    pub mean_us: f64,
+    /// Length of the shortest frame
+    pub min_us: u64,
+    /// Length of the longest (worst) frame
+    pub max_us: u64,
+    /// Median length of the frames
+    pub p50_us: u64,
+    /// 99th percentile of the frame lengths
+    pub p99_us: u64,
+    /// Length of the most recent frame
+    pub last_us: u64,
+}
+
+impl FrameStats {
+    /// Calculates the statistics of frame lengths given oldest first, empty for no frames
+    pub fn from_lengths<'a, I: IntoIterator<Item = &'a u64>>(lengths: I) -> FrameStats {
+        let mut sorted: Vec<u64> = lengths.into_iter().cloned().collect();
+
+        let last_us = match sorted.last() {
+            Some(&last_us) => last_us,
+            None => return FrameStats::default(),
+        };
+
+        sorted.sort_unstable();
+
+        let mean_us = sorted.iter().sum::<u64>() as f64 / sorted.len() as f64;
+
+        FrameStats {
+            frames: sorted.len(),
+            fps: if mean_us > 0.0 { 1_000_000.0 / mean_us } else { 0.0 },
+            mean_us,
+            min_us: sorted[0],
+            max_us: sorted[sorted.len() - 1],
+            p50_us: percentile(&sorted, 50.0),
+            p99_us: percentile(&sorted, 99.0),
+            last_us,
+        }
+    }
+}
+
+// Nearest-rank percentile of non-empty sorted lengths
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Ends a frame when dropped, see ``Remotery::mark_frame``
+///
+/// # Examples
+///
+/// ```ignore
+/// loop {
+///     let _frame = FrameScope::new();
+///     tick();
+/// }
+/// ```
+///
+pub struct FrameScope {
+    _private: (),
+}
+
+impl FrameScope {
+    /// Creates a scope that ends the current frame when it goes out of scope
+    pub fn new() -> FrameScope {
+        FrameScope { _private: () }
+    }
+}
+
+impl Default for FrameScope {
+    fn default() -> FrameScope {
+        FrameScope::new()
+    }
+}
+
+impl Drop for FrameScope {
+    fn drop(&mut self) {
+        Remotery::mark_frame();
+    }
+}
+
+#[cfg(feature = "enabled")]
+struct History {
+    running: bool,
+    us_start: u64,
+    lengths: VecDeque<u64>,
+    capacity: usize,
+}
+
+// Frames of the running instance, there is only one instance at a time
+#[cfg(feature = "enabled")]
+static HISTORY: Mutex<History> = Mutex::new(History {
+    running: false,
+    us_start: 0,
+    lengths: VecDeque::new(),
+    capacity: 0,
+});
+
+///
+/// Ends the current frame and starts the next one. Only the index and length are taken while
+/// holding the history so frames marked from different threads stay consistent, the ``FRAME``
+/// message is queued for the Remotery thread which sends it and writes it to the capture file.
+/// Does nothing without a running instance.
+///
+#[cfg(feature = "enabled")]
+pub(crate) fn mark_frame() {
+    let (frame_index, us_start, us_length) = {
+        let mut history = HISTORY.lock().unwrap_or_else(|e| e.into_inner());
+
+        if !history.running {
+            return;
+        }
+
+        let (frame_index, us_end) = unsafe { (remotery_ffi::_rmt_MarkFrame(), remotery_ffi::_rmt_GetTime()) };
+        let us_start = history.us_start;
+        let us_length = us_end.saturating_sub(us_start);
+
+        history.us_start = us_end;
+
+        if history.lengths.len() == history.capacity {
+            history.lengths.pop_front();
+        }
+
+        history.lengths.push_back(us_length);
+
+        (frame_index, us_start, us_length)
+    };
+
+    unsafe { remotery_ffi::_rmt_SendFrame(frame_index, us_start, us_length) };
+}
+
+/// Returns the statistics of the frames in the history of the running instance
+#[cfg(feature = "enabled")]
+pub(crate) fn stats() -> FrameStats {
+    FrameStats::from_lengths(&HISTORY.lock().unwrap_or_else(|e| e.into_inner()).lengths)
+}
+
+/// Owned by the ``Remotery`` instance, frames are only recorded while it's alive
+#[cfg(feature = "enabled")]
+pub(crate) struct Frames {
+    _private: (),
+}
+
+#[cfg(feature = "enabled")]
+impl Frames {
+    /// Starts the first frame and keeps the lengths of the last ``capacity`` frames
+    pub fn start(capacity: usize) -> Frames {
+        *HISTORY.lock().unwrap_or_else(|e| e.into_inner()) = History {
+            running: true,
+            us_start: unsafe { remotery_ffi::_rmt_GetTime() },
+            lengths: VecDeque::with_capacity(capacity),
+            capacity,
+        };
+
+        Frames { _private: () }
+    }
+}
+
+#[cfg(feature = "enabled")]
+impl Drop for Frames {
+    fn drop(&mut self) {
+        let mut history = HISTORY.lock().unwrap_or_else(|e| e.into_inner());
+        history.running = false;
+        history.lengths.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_stats() {
+        assert_eq!(FrameStats::from_lengths(&[]), FrameStats::default());
+
+        let mut lengths = vec![16_000; 99];
+        lengths.push(40_000);
+        lengths.push(20_000);
+
+        let stats = FrameStats::from_lengths(&lengths);
+
+        assert_eq!(stats.frames, 101);
+        assert_eq!(stats.min_us, 16_000);
+        assert_eq!(stats.max_us, 40_000);
+        assert_eq!(stats.p50_us, 16_000);
+        assert_eq!(stats.p99_us, 20_000);
+        assert_eq!(stats.last_us, 20_000);
+        assert!((stats.fps - 1_000_000.0 / stats.mean_us).abs() < 1e-9);
+        assert!(stats.fps > 60.0 && stats.fps < 62.5);
+    }
+
+    #[test]
+    fn test_percentile() {
+        assert_eq!(percentile(&[7], 50.0), 7);
+        assert_eq!(percentile(&[7], 99.0), 7);
+        assert_eq!(percentile(&[1, 2, 3, 4], 50.0), 2);
+        assert_eq!(percentile(&[1, 2, 3, 4], 99.0), 4);
+        assert_eq!(percentile(&[1, 2, 3, 4], 0.0), 1);
+    }
+}
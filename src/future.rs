@@ -0,0 +1,122 @@
+//! Profiling of futures. A ``RemoteryScope`` can't be held across an ``.await`` as the task may be
+//! resumed on another thread, so instead the future is wrapped and a cpu sample is begun and ended
+//! around each ``poll``. This way every poll shows up in the viewer on the thread that ran it.
+//!
+//! There is no sample covering the task from its first poll until it completes. Remotery samples
+//! nest per thread and must end on the thread they were begun on, so the time a task spends
+//! waiting between polls isn't shown and its polls aren't grouped under a parent of their own.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! use remotery::future::RemoteryFutureExt;
+//!
+//! let bytes = load_file("level1.map").remotery("load_level").await;
+//! ```
+//!
+use std::ffi::CString;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::AtomicU32;
+use std::task::{Context, Poll};
+use {RemoteryScope, SampleFlags};
+
+/// Future that begins a cpu sample each time it's polled, see ``RemoteryFutureExt``
+#[must_use = "futures do nothing unless polled"]
+pub struct Instrumented<F> {
+    inner: F,
+    name: CString,
+    flags: SampleFlags,
+    hash_cache: AtomicU32,
+}
+
+impl<F> Instrumented<F> {
+    /// Returns the wrapped future
+    pub fn into_inner(self) -> F {
+        self.inner
+    }
+}
+
+impl<F: Future> Future for Instrumented<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        // Safe as ``inner`` is never moved out of a pinned ``Instrumented`` and there is no Drop impl
+        let this = unsafe { self.get_unchecked_mut() };
+        let inner = unsafe { Pin::new_unchecked(&mut this.inner) };
+
+        // The scope also ends the sample if the inner future panics
+        let _scope = RemoteryScope::new_cstr(&this.name, this.flags, &this.hash_cache);
+        inner.poll(cx)
+    }
+}
+
+/// Adds profiling of each ``poll`` to all futures
+pub trait RemoteryFutureExt: Future + Sized {
+    ///
+    /// Begins a cpu sample named ``name`` each time the future is polled and ends it once the poll
+    /// returns.
+    ///
+    fn remotery(self, name: &str) -> Instrumented<Self> {
+        self.remotery_with_flags(name, SampleFlags::Default)
+    }
+
+    ///
+    /// Same as ``remotery`` but uses ``SampleFlags::Aggregate`` so polls that run within the
+    /// same parent sample on the same thread (such as one tick of the executor) are merged into a
+    /// single sample. Polls on other threads or in other ticks still show up as samples of their
+    /// own, see the module docs.
+    ///
+    fn remotery_aggregate(self, name: &str) -> Instrumented<Self> {
+        self.remotery_with_flags(name, SampleFlags::Aggregate)
+    }
+
+    /// Same as ``remotery`` with the flags used for the samples
+    fn remotery_with_flags(self, name: &str, flags: SampleFlags) -> Instrumented<Self> {
+        Instrumented {
+            inner: self,
+            name: CString::new(name.replace('\0', "")).unwrap_or_default(),
+            flags,
+            hash_cache: AtomicU32::new(0),
+        }
+    }
+}
+
+impl<F: Future> RemoteryFutureExt for F {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Waker;
+
+    // Returns ``Pending`` the given number of times before completing
+    struct Yield(u32);
+
+    impl Future for Yield {
+        type Output = &'static str;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<&'static str> {
+            if self.0 == 0 {
+                return Poll::Ready("done");
+            }
+
+            self.0 -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    fn assert_send<T: Send>(_: &T) {}
+
+    #[test]
+    fn test_instrumented_polls() {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut future = Box::pin(Yield(2).remotery("load\0level"));
+
+        assert_send(&future);
+        assert_eq!(future.name.to_bytes(), b"loadlevel");
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Ready("done"));
+    }
+}
@@ -0,0 +1,90 @@
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+use Remotery;
+
+pub type InputHandlerFn = Box<dyn Fn(&str) + Send + Sync>;
+
+/// Owned by the ``Remotery`` instance and handed to the C side as ``input_handler_context``.
+/// The handler is kept behind a lock so it can be replaced while the server thread is running.
+pub struct InputHandler {
+    handler: Mutex<Option<InputHandlerFn>>,
+}
+
+impl InputHandler {
+    pub fn new() -> InputHandler {
+        InputHandler {
+            handler: Mutex::new(None),
+        }
+    }
+
+    pub fn set(&self, handler: InputHandlerFn) {
+        let mut current = self.handler.lock().unwrap_or_else(|e| e.into_inner());
+        *current = Some(handler);
+    }
+
+    fn call(&self, text: &str) {
+        let current = self.handler.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(ref handler) = *current {
+            handler(text);
+        }
+    }
+}
+
+///
+/// Called by the Remotery server thread for each console message (``CONI``) sent by the viewer.
+/// Panics must not unwind into the C code so they are caught here and reported to the viewer.
+///
+pub unsafe extern "C" fn input_handler_callback(text: *const c_char, context: *mut c_void) {
+    if text.is_null() || context.is_null() {
+        return;
+    }
+
+    let handler = &*(context as *const InputHandler);
+    let text = CStr::from_ptr(text).to_string_lossy();
+
+    if panic::catch_unwind(AssertUnwindSafe(|| handler.call(&text))).is_err() {
+        Remotery::log_text("Remotery console input handler panicked");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn call(handler: &InputHandler, text: &[u8]) {
+        unsafe { input_handler_callback(text.as_ptr() as *const c_char, handler as *const InputHandler as *mut c_void) };
+    }
+
+    #[test]
+    fn test_invalid_utf8_is_replaced() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let handler = InputHandler::new();
+        let sink = received.clone();
+        handler.set(Box::new(move |text| sink.lock().unwrap().push(text.to_owned())));
+
+        call(&handler, b"reload \xff shaders\0");
+
+        assert_eq!(*received.lock().unwrap(), ["reload \u{FFFD} shaders"]);
+    }
+
+    #[test]
+    fn test_panicking_handler_is_caught() {
+        let handler = InputHandler::new();
+        handler.set(Box::new(|_| panic!("bad console input")));
+
+        call(&handler, b"crash\0");
+
+        // The handler keeps working after a panic
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        handler.set(Box::new(move |text| sink.lock().unwrap().push(text.to_owned())));
+
+        call(&handler, b"still alive\0");
+
+        assert_eq!(*received.lock().unwrap(), ["still alive"]);
+    }
+}
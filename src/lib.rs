@@ -2,17 +2,113 @@
 //! This lib is a [Rust](https://www.rust-lang.org) wrapper around the C API provided by Remotery and the original
 //! repo over here https://github.com/Celtoys/Remotery
 //!
+//! All profiling is compiled out to no-ops (and the C code isn't built) when the default
+//! ``enabled`` feature is turned off.
+//!
+#[macro_use]
+extern crate serde;
+extern crate serde_json;
+#[cfg(feature = "macros")]
+extern crate remotery_macros;
+#[cfg(feature = "tracing")]
+extern crate tracing_core;
+#[cfg(feature = "tracing")]
+extern crate tracing_subscriber;
+#[cfg(feature = "logger")]
+extern crate log;
+#[macro_use]
+mod macros;
 pub mod error;
+pub mod settings;
+pub mod console;
+pub mod future;
+pub mod thread;
+pub mod sample_tree;
+pub mod capture;
+pub mod counters;
+pub mod frames;
+pub mod export;
+pub mod client;
+pub mod stats;
+#[cfg(feature = "enabled")]
+pub mod testing;
+#[cfg(feature = "tracing")]
+pub mod tracing;
+#[cfg(feature = "logger")]
+pub mod logger;
 mod remotery_ffi;
 mod cfixed_string;
-use std::ptr;
+#[cfg(feature = "enabled")]
+mod input;
+mod memory;
+#[cfg(feature = "enabled")]
+mod viewer;
+#[cfg(all(feature = "enabled", debug_assertions))]
+mod sample_stack;
+#[cfg(all(feature = "enabled", debug_assertions))]
+use sample_stack::SampleName;
+#[cfg(not(feature = "enabled"))]
+mod disabled;
+use std::any;
+use std::ffi::CString;
+use std::marker::PhantomData;
 use std::os::raw::c_void;
+pub use settings::RemoterySettings;
+pub use capture::CaptureMode;
+pub use frames::FrameScope;
+#[cfg(feature = "macros")]
+pub use remotery_macros::profile;
+#[cfg(feature = "enabled")]
+use std::ptr;
+#[cfg(feature = "enabled")]
+use std::ffi::CStr;
+#[cfg(feature = "enabled")]
+use std::net::SocketAddr;
+#[cfg(feature = "enabled")]
+use std::os::raw::c_char;
+#[cfg(feature = "enabled")]
+use std::sync::atomic::AtomicU32;
+#[cfg(feature = "enabled")]
+use std::sync::mpsc::{self, Receiver};
+#[cfg(feature = "enabled")]
+use std::sync::Arc;
+#[cfg(feature = "enabled")]
 use error::RemoteryError;
+#[cfg(feature = "enabled")]
 use cfixed_string::CFixedString;
+#[cfg(feature = "enabled")]
+use settings::AppliedSettings;
+#[cfg(feature = "enabled")]
+use input::InputHandler;
+#[cfg(feature = "enabled")]
+use sample_tree::{SampleTree, SampleTreeHandlers};
+#[cfg(feature = "enabled")]
+use capture::{CaptureFile, Record};
+#[cfg(feature = "enabled")]
+use counters::{Counter, Gauge, Snapshots};
+#[cfg(feature = "enabled")]
+use frames::{Frames, FrameStats};
 
 /// Holds the main instance for Remotery
 pub struct Remotery {
+    // Always null without the ``enabled`` feature, still there so ``Remotery`` is ``!Send`` either way
+    #[cfg_attr(not(feature = "enabled"), allow(dead_code))]
     instance: *mut c_void,
+    // Needs to stay alive while the instance is running as the C side points into it
+    #[cfg(feature = "enabled")]
+    _settings: AppliedSettings,
+    #[cfg(feature = "enabled")]
+    input_handler: Box<InputHandler>,
+    #[cfg(feature = "enabled")]
+    sample_tree_handlers: Box<SampleTreeHandlers>,
+    #[cfg(feature = "enabled")]
+    capture: Option<Arc<CaptureFile>>,
+    #[cfg(feature = "enabled")]
+    local_addr: SocketAddr,
+    #[cfg(feature = "enabled")]
+    counter_snapshots: Option<Snapshots>,
+    #[cfg(feature = "enabled")]
+    frames: Option<Frames>,
 }
 
 #[derive(Clone, Copy)]
@@ -24,6 +120,7 @@ pub enum SampleFlags {
 	Aggregate,
 }
 
+#[cfg(feature = "enabled")]
 impl Remotery {
 	/// Creates the global instance (with in the C lib that this code wraps) this code needs to be
 	/// called before any of the other code is being called and the instance will be dropped when
@@ -34,22 +131,212 @@ impl Remotery {
 	///
 	/// ```ignore
     /// let _remotery = Remotery::create_global_instance().unwrap_or_else(|e| {
-    ///  	panic!(e);
+    ///     panic!("{}", e);
 	/// });
 	/// ```
 	///
     pub fn create_global_instance() -> Result<Remotery, RemoteryError> {
-        let mut instance = 0 as *mut c_void;
+        Self::create_global_instance_with(RemoterySettings::new())
+    }
+
+    /// Same as ``create_global_instance`` but allows the settings of the instance (such as the
+    /// port to listen on) to be changed. Returns ``RemoteryError::InvalidSettings`` if any of the
+    /// values in ``settings`` can't be used.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let settings = RemoterySettings::new().port(17816);
+    /// let _remotery = Remotery::create_global_instance_with(settings).unwrap();
+    /// ```
+    ///
+    pub fn create_global_instance_with(settings: RemoterySettings) -> Result<Remotery, RemoteryError> {
+        let mut instance = ptr::null_mut();
+
+        let applied = unsafe { settings.apply(remotery_ffi::_rmt_Settings())? };
+
+        let capture = match *settings.get_capture_mode() {
+            CaptureMode::Viewer => None,
+            CaptureMode::File(ref path) => Some(Arc::new(CaptureFile::create(path)?)),
+        };
+
+        // Callbacks are in place before the server thread starts so it never sees them change,
+        // setting a handler later only swaps it on the Rust side
+        let input_handler = Box::new(InputHandler::new());
+        let sample_tree_handlers = Box::new(SampleTreeHandlers::new());
+
+        unsafe {
+            let settings = &mut *remotery_ffi::_rmt_Settings();
+            settings.input_handler_context = &*input_handler as *const InputHandler as *mut c_void;
+            settings.input_handler = Some(input::input_handler_callback);
+            settings.sampletree_context = &*sample_tree_handlers as *const SampleTreeHandlers as *mut c_void;
+            settings.sampletree_handler = Some(sample_tree::sample_tree_callback);
+        }
+
+        if let Some(ref capture) = capture {
+            start_capture(capture, &sample_tree_handlers);
+        }
 
         let res = unsafe {
             remotery_ffi::_rmt_CreateGlobalInstance(&mut instance)
         };
 
         if res != 0 {
+            unsafe { remove_handlers() };
             return Err(error::get_error(res));
         }
 
-        Ok(Remotery { instance: instance })
+        // The address the OS actually bound, the one in the settings may be unspecified or port 0
+        let mut host = [0 as c_char; 64];
+        let mut port = 0;
+        let bound = unsafe {
+            remotery_ffi::_rmt_GetServerAddress(instance, host.as_mut_ptr(), host.len() as u32, &mut port) != 0
+        };
+        let ip = if bound {
+            unsafe { CStr::from_ptr(host.as_ptr()) }.to_str().ok().and_then(|host| host.parse().ok())
+        } else {
+            None
+        };
+
+        let mut remotery = Remotery {
+            instance,
+            _settings: applied,
+            input_handler,
+            sample_tree_handlers,
+            capture,
+            local_addr: SocketAddr::new(ip.unwrap_or_else(|| settings.get_bind_ip()), port),
+            counter_snapshots: None,
+            frames: None,
+        };
+
+        remotery.counter_snapshots = Some(Snapshots::start(settings.get_counter_interval(), remotery.capture.clone()));
+        remotery.frames = Some(Frames::start(settings.get_frame_history()));
+
+        Ok(remotery)
+    }
+
+    ///
+    /// Sets a handler that gets called with the text typed into the console of the Remotery viewer.
+    /// The handler is called from the Remotery server thread and will be freed when the instance is
+    /// dropped. Setting a new handler replaces the previous one. Invalid UTF-8 in the text is
+    /// replaced with ``U+FFFD`` and panics in the handler are caught and logged to the viewer.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// remotery.set_input_handler(|text| {
+    ///     println!("console: {}", text);
+    /// });
+    /// ```
+    ///
+    pub fn set_input_handler<F>(&mut self, handler: F) where F: Fn(&str) + Send + Sync + 'static {
+        self.input_handler.set(Box::new(handler));
+    }
+
+    ///
+    /// Adds a handler that gets called with each completed sample tree, whether or not a viewer is
+    /// connected. A tree is completed when the outermost sample of a thread ends. The handler is
+    /// called from the Remotery thread (so it should return quickly) and will be freed when the
+    /// instance is dropped. Panics in the handler are caught and logged to the viewer.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// remotery.add_sample_tree_handler(|tree| {
+    ///     println!("{}: {} took {} us", tree.thread_name, tree.root.name, tree.root.us_length);
+    /// });
+    /// ```
+    ///
+    pub fn add_sample_tree_handler<F>(&mut self, handler: F) where F: Fn(&SampleTree) + Send + Sync + 'static {
+        self.sample_tree_handlers.add(Box::new(handler));
+    }
+
+    ///
+    /// Returns a channel that receives each completed sample tree, see ``add_sample_tree_handler``.
+    /// The channel is closed once the instance is dropped.
+    ///
+    pub fn sample_tree_channel(&mut self) -> Receiver<SampleTree> {
+        let (sender, receiver) = mpsc::channel();
+
+        self.add_sample_tree_handler(move |tree| {
+            let _ = sender.send(tree.clone());
+        });
+
+        receiver
+    }
+
+    ///
+    /// Returns the address the server listens on for viewers, including the port picked by the OS
+    /// when the instance was created with port 0.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let remotery = Remotery::create_global_instance_with(RemoterySettings::new().port(0)).unwrap();
+    /// println!("Remotery listening on {}", remotery.local_addr());
+    /// ```
+    ///
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    ///
+    /// Returns the counter called ``name``, creating it the first time. Counters are integers that
+    /// are sent to the viewers together with all other counters and gauges, see the ``counters``
+    /// module. Every call with the same name returns a handle to the same value. Panics if
+    /// ``name`` is already used by a gauge.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let entities = Remotery::counter("entities");
+    /// entities.set(123);
+    /// entities.add(5);
+    /// ```
+    ///
+    pub fn counter(name: &str) -> Counter {
+        counters::counter(name)
+    }
+
+    ///
+    /// Returns the gauge called ``name``, creating it the first time. Gauges are the floating
+    /// point version of ``counter``. Panics if ``name`` is already used by a counter.
+    ///
+    pub fn gauge(name: &str) -> Gauge {
+        counters::gauge(name)
+    }
+
+    /// Sends a snapshot of all counters and gauges now instead of waiting for the next interval
+    pub fn send_counters() {
+        counters::request_snapshot();
+    }
+
+    ///
+    /// Ends the current frame and starts the next one, see the ``frames`` module. Sample trees
+    /// completed after this are stamped with the next frame index. ``FrameScope`` calls this when
+    /// it goes out of scope.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// loop {
+    ///     update();
+    ///     render();
+    ///     Remotery::mark_frame();
+    /// }
+    /// ```
+    ///
+    pub fn mark_frame() {
+        frames::mark_frame();
+    }
+
+    ///
+    /// Returns statistics such as the average FPS, the worst frame and the 99th percentile of the
+    /// most recent frames (see ``RemoterySettings::frame_history``). Empty without an instance or
+    /// before the first frame has been marked.
+    ///
+    pub fn frame_stats() -> FrameStats {
+        frames::stats()
     }
 
     ///
@@ -64,16 +351,47 @@ impl Remotery {
     /// ```
     ///
     pub fn begin_cpu_sample(name: &str, flags: SampleFlags) {
-        // TODO: As we send 0 as last parameter which is hash caching this will always recalculate
-        // the hash which adds some slight overhead. Would be nice if that could be solved somehow.
+        #[cfg(debug_assertions)]
+        sample_stack::push(SampleName::Owned(name.to_owned()));
+
+        thread::auto_name_current();
+
+        // As we send 0 as last parameter which is hash caching this will always recalculate
+        // the hash which adds some slight overhead. Use ``cpu_sample!`` for names known at
+        // compile time to avoid it.
         unsafe {
             let temp_str = CFixedString::from_str(name);
             remotery_ffi::_rmt_BeginCPUSample(temp_str.as_ptr(), flags as u32, ptr::null_mut());
         }
     }
 
+    ///
+    /// Begin a cpu sample with a name that is already nul-terminated and a place to cache the
+    /// hash of the name. The hash is only calculated the first time ``hash_cache`` is used so the
+    /// same cache must not be used for different names. This is what ``cpu_sample!`` uses and
+    /// it needs to be paired with ``end_cpu_sample``. Nothing is allocated or copied, not even in
+    /// debug builds.
+    ///
+    pub fn begin_cpu_sample_cached(name: &'static CStr, flags: SampleFlags, hash_cache: &AtomicU32) {
+        #[cfg(debug_assertions)]
+        sample_stack::push(SampleName::Static(name));
+
+        begin_cached(name, flags, hash_cache);
+    }
+
+    // Same as ``begin_cpu_sample_cached`` for names that only live as long as their owner
+    pub(crate) fn begin_cpu_sample_cstr(name: &CStr, flags: SampleFlags, hash_cache: &AtomicU32) {
+        #[cfg(debug_assertions)]
+        sample_stack::push(SampleName::Owned(name.to_string_lossy().into_owned()));
+
+        begin_cached(name, flags, hash_cache);
+    }
+
     /// Ends a cpu sample. Notice that this needs to be paired with ``begin_cpu_sample`` as seen above.
     pub fn end_cpu_sample() {
+        #[cfg(debug_assertions)]
+        sample_stack::pop();
+
         unsafe {
             remotery_ffi::_rmt_EndCPUSample();
         }
@@ -88,6 +406,8 @@ impl Remotery {
     /// ```
     ///
     pub fn set_current_thread_name(name: &str) {
+        thread::mark_named();
+
         unsafe {
             let temp_str = CFixedString::from_str(name);
             remotery_ffi::_rmt_SetCurrentThreadName(temp_str.as_ptr());
@@ -105,9 +425,26 @@ impl Remotery {
     }
 }
 
-/// Scopes allows you to profile a bit of code and the end_cpu_sample will be called once it goes out of scope
-pub struct RemoteryScope;
+///
+/// Scopes allows you to profile a bit of code and the end_cpu_sample will be called once it goes out of scope.
+///
+/// Remotery keeps the open samples of each thread in a stack so a scope can't be sent to another
+/// thread, which also means it can't be held across an ``.await`` in a ``Send`` future. In debug
+/// builds scopes also check that they are ended in the reverse order they were begun and panic
+/// (or log to the viewer if the thread already is panicking) when they aren't.
+///
+/// ```compile_fail
+/// let scope = remotery::RemoteryScope::new("main", remotery::SampleFlags::Default);
+/// std::thread::spawn(move || drop(scope));
+/// ```
+///
+pub struct RemoteryScope {
+    _not_send: PhantomData<*const ()>,
+    #[cfg(all(feature = "enabled", debug_assertions))]
+    open: sample_stack::OpenSample,
+}
 
+#[cfg(feature = "enabled")]
 impl RemoteryScope {
 	///
 	/// Begin a new Scope which auto calls ``end_cpu_scope`` once the scope ends
@@ -119,25 +456,191 @@ impl RemoteryScope {
     /// ```
     pub fn new(name: &str, flags: SampleFlags) -> RemoteryScope {
         Remotery::begin_cpu_sample(name, flags);
-        RemoteryScope {}
+
+        RemoteryScope {
+            _not_send: PhantomData,
+            #[cfg(debug_assertions)]
+            open: sample_stack::OpenSample::current(SampleName::Owned(name.to_owned())),
+        }
+    }
+
+    ///
+    /// Begin a new Scope using a nul-terminated name and hash cache, see
+    /// ``Remotery::begin_cpu_sample_cached``. This is what ``scoped_cpu_sample!`` uses.
+    ///
+    pub fn new_cached(name: &'static CStr, flags: SampleFlags, hash_cache: &AtomicU32) -> RemoteryScope {
+        Remotery::begin_cpu_sample_cached(name, flags, hash_cache);
+
+        RemoteryScope {
+            _not_send: PhantomData,
+            #[cfg(debug_assertions)]
+            open: sample_stack::OpenSample::current(SampleName::Static(name)),
+        }
+    }
+
+    // Same as ``new_cached`` for names that only live as long as their owner
+    pub(crate) fn new_cstr(name: &CStr, flags: SampleFlags, hash_cache: &AtomicU32) -> RemoteryScope {
+        Remotery::begin_cpu_sample_cstr(name, flags, hash_cache);
+
+        RemoteryScope {
+            _not_send: PhantomData,
+            #[cfg(debug_assertions)]
+            open: sample_stack::OpenSample::current(SampleName::Owned(name.to_string_lossy().into_owned())),
+        }
     }
 }
 
+#[cfg(feature = "enabled")]
 impl Drop for RemoteryScope {
     fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        {
+            if !self.open.check_end() {
+                return;
+            }
+        }
+
         Remotery::end_cpu_sample()
     }
 }
 
+#[cfg(feature = "enabled")]
 impl Drop for Remotery {
     fn drop(&mut self) {
-        if self.instance == ptr::null_mut() {
+        if self.instance.is_null() {
             return
         }
 
+        // Stop sending snapshots and frames before the queue they are sent on goes away
+        self.counter_snapshots = None;
+        self.frames = None;
+
         unsafe {
             remotery_ffi::_rmt_DestroyGlobalInstance(self.instance);
+
+            // The server thread is gone so the handlers can't be called anymore
+            remove_handlers();
         }
     }
 }
 
+// Begins a sample whose name hash is cached in ``hash_cache``
+#[cfg(feature = "enabled")]
+#[inline]
+fn begin_cached(name: &CStr, flags: SampleFlags, hash_cache: &AtomicU32) {
+    thread::auto_name_current();
+
+    unsafe {
+        remotery_ffi::_rmt_BeginCPUSample(name.as_ptr(), flags as u32, hash_cache.as_ptr());
+    }
+}
+
+// Name of the function ``__scoped_fn_sample!`` was expanded in, from the path of the function
+// item it declares inside of it
+#[doc(hidden)]
+pub fn __function_name<F>(_: F) -> CString {
+    let path = any::type_name::<F>();
+    let name = path.strip_suffix("::__rmt_fn").unwrap_or(path).replace("::{{closure}}", "");
+
+    CString::new(name).unwrap_or_default()
+}
+
+// Stops the C side from calling into the handlers of an instance that is going away
+#[cfg(feature = "enabled")]
+unsafe fn remove_handlers() {
+    let settings = &mut *remotery_ffi::_rmt_Settings();
+    settings.input_handler = None;
+    settings.input_handler_context = ptr::null_mut();
+    settings.sampletree_handler = None;
+    settings.sampletree_context = ptr::null_mut();
+    settings.loghandler = None;
+    settings.loghandler_context = ptr::null_mut();
+    settings.frame_handler = None;
+    settings.frame_handler_context = ptr::null_mut();
+}
+
+// Writes all sample trees, log lines and frames to ``capture`` from the Remotery thread
+#[cfg(feature = "enabled")]
+fn start_capture(capture: &Arc<CaptureFile>, sample_tree_handlers: &SampleTreeHandlers) {
+    let file = capture.clone();
+    sample_tree_handlers.add(Box::new(move |tree| file.write(&Record::from_sample_tree(tree))));
+
+    unsafe {
+        let settings = &mut *remotery_ffi::_rmt_Settings();
+        settings.loghandler_context = &**capture as *const CaptureFile as *mut c_void;
+        settings.loghandler = Some(capture::log_callback);
+        settings.frame_handler_context = &**capture as *const CaptureFile as *mut c_void;
+        settings.frame_handler = Some(capture::frame_callback);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_macros_without_instance() {
+        for _ in 0..2 {
+            scoped_cpu_sample!("outer");
+            __scoped_fn_sample!(SampleFlags::Default);
+            cpu_sample!("inner", SampleFlags::Aggregate);
+            Remotery::end_cpu_sample();
+        }
+
+        assert_eq!(__sample_name!("name").to_bytes(), b"name");
+    }
+
+    struct World<T>(T);
+
+    trait System {
+        fn run(&self) -> CString;
+    }
+
+    impl<T> World<T> {
+        fn update(&self) -> CString {
+            fn __rmt_fn() {}
+            __function_name(__rmt_fn)
+        }
+    }
+
+    impl<T> System for World<T> {
+        fn run(&self) -> CString {
+            fn __rmt_fn() {}
+            __function_name(__rmt_fn)
+        }
+    }
+
+    fn generic<T>(_: T) -> CString {
+        let name = || {
+            fn __rmt_fn() {}
+            __function_name(__rmt_fn)
+        };
+
+        name()
+    }
+
+    #[test]
+    fn test_function_names() {
+        let world = World(1u8);
+
+        assert_eq!(world.update().to_str().unwrap(), "remotery::tests::World<_>::update");
+        assert_eq!(world.run().to_str().unwrap(), "<remotery::tests::World<_> as remotery::tests::System>::run");
+        assert_eq!(generic(1u32).to_str().unwrap(), "remotery::tests::generic");
+    }
+
+    #[test]
+    #[cfg(all(feature = "enabled", debug_assertions))]
+    #[should_panic(expected = "RemoteryScope \"outer\" ended out of order while \"inner\" is still open")]
+    fn test_scopes_out_of_order() {
+        let outer = RemoteryScope::new("outer", SampleFlags::Default);
+        let _inner = RemoteryScope::new("inner", SampleFlags::Default);
+        drop(outer);
+    }
+
+    #[test]
+    #[cfg(all(feature = "enabled", debug_assertions))]
+    #[should_panic(expected = "without an open sample")]
+    fn test_unmatched_end() {
+        Remotery::end_cpu_sample();
+    }
+}
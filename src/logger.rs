@@ -0,0 +1,146 @@
+//! A [log](https://docs.rs/log) backend that sends each record to the Remotery console as a
+//! ``[LEVEL target] message`` line, so log output shows up in the viewer next to the samples that
+//! produced it. Records can also be passed on to another logger (such as ``env_logger``).
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let _remotery = Remotery::create_global_instance().unwrap();
+//!
+//! RemoteryLogger::new()
+//!     .with_level(LevelFilter::Debug)
+//!     .with_inner(env_logger::Builder::from_default_env().build())
+//!     .init()
+//!     .unwrap();
+//! ```
+//!
+use log::{self, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use Remotery;
+
+/// Logger that mirrors records to the Remotery console
+pub struct RemoteryLogger {
+    level: LevelFilter,
+    inner: Option<Box<dyn Log>>,
+}
+
+impl Default for RemoteryLogger {
+    fn default() -> RemoteryLogger {
+        RemoteryLogger::new()
+    }
+}
+
+fn format_record(record: &Record) -> String {
+    format!("[{} {}] {}", record.level(), record.target(), record.args())
+}
+
+impl RemoteryLogger {
+    /// Creates a logger that sends all records with ``Info`` level or higher to Remotery
+    pub fn new() -> RemoteryLogger {
+        RemoteryLogger {
+            level: LevelFilter::Info,
+            inner: None,
+        }
+    }
+
+    /// Sets the most verbose level of records that are sent to Remotery
+    pub fn with_level(mut self, level: LevelFilter) -> RemoteryLogger {
+        self.level = level;
+        self
+    }
+
+    /// Passes all records on to ``inner`` as well. ``inner`` does its own filtering.
+    pub fn with_inner<L: Log + 'static>(mut self, inner: L) -> RemoteryLogger {
+        self.inner = Some(Box::new(inner));
+        self
+    }
+
+    ///
+    /// Installs this as the global logger. The max level of the ``log`` crate is set to the
+    /// level of this logger, or to ``Trace`` if there is an inner logger as its level isn't known.
+    ///
+    pub fn init(self) -> Result<(), SetLoggerError> {
+        let max_level = if self.inner.is_some() { LevelFilter::Trace } else { self.level };
+
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+}
+
+impl Log for RemoteryLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level || self.inner.as_ref().is_some_and(|inner| inner.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= self.level {
+            Remotery::log_text(&format_record(record));
+        }
+
+        if let Some(ref inner) = self.inner {
+            if inner.enabled(record.metadata()) {
+                inner.log(record);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(ref inner) = self.inner {
+            inner.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+    use std::sync::{Arc, Mutex};
+
+    struct Capture(Arc<Mutex<Vec<String>>>);
+
+    impl Log for Capture {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= Level::Debug
+        }
+
+        fn log(&self, record: &Record) {
+            self.0.lock().unwrap().push(format_record(record));
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn record(level: Level, target: &str, f: &dyn Fn(&Record)) {
+        f(&Record::builder()
+            .level(level)
+            .target(target)
+            .args(format_args!("loaded {} assets", 3))
+            .build())
+    }
+
+    #[test]
+    fn test_format_record() {
+        record(Level::Warn, "game::assets", &|r| {
+            assert_eq!(format_record(r), "[WARN game::assets] loaded 3 assets");
+        });
+    }
+
+    #[test]
+    fn test_level_filtering_and_inner() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let logger = RemoteryLogger::new()
+            .with_level(LevelFilter::Warn)
+            .with_inner(Capture(captured.clone()));
+
+        record(Level::Error, "game", &|r| assert!(logger.enabled(r.metadata())));
+        record(Level::Debug, "game", &|r| assert!(logger.enabled(r.metadata())));
+        record(Level::Trace, "game", &|r| assert!(!logger.enabled(r.metadata())));
+
+        record(Level::Info, "game", &|r| logger.log(r));
+        record(Level::Trace, "game", &|r| logger.log(r));
+
+        assert_eq!(*captured.lock().unwrap(), vec!["[INFO game] loaded 3 assets".to_owned()]);
+        assert!(!RemoteryLogger::new().with_level(LevelFilter::Off).enabled(&Metadata::builder().level(Level::Error).build()));
+    }
+}
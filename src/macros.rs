@@ -0,0 +1,92 @@
+///
+/// Begin a cpu sample with a name that is known at compile time. Each call site gets its own
+/// static hash cache and a nul-terminated copy of the name so no hashing or copying of the name is
+/// done when the sample is begun. Needs to be paired with ``Remotery::end_cpu_sample``.
+///
+/// # Examples
+///
+/// ```ignore
+/// cpu_sample!("update");
+/// // some code to profile here
+/// Remotery::end_cpu_sample();
+///
+/// cpu_sample!("physics", SampleFlags::Aggregate);
+/// Remotery::end_cpu_sample();
+/// ```
+///
+#[macro_export]
+macro_rules! cpu_sample {
+    ($name:expr) => {
+        $crate::cpu_sample!($name, $crate::SampleFlags::Default)
+    };
+    ($name:expr, $flags:expr) => {{
+        static RMT_SAMPLE_HASH: ::std::sync::atomic::AtomicU32 = ::std::sync::atomic::AtomicU32::new(0);
+        static RMT_SAMPLE_NAME: &::std::ffi::CStr = $crate::__sample_name!($name);
+        $crate::Remotery::begin_cpu_sample_cached(RMT_SAMPLE_NAME, $flags, &RMT_SAMPLE_HASH);
+    }};
+}
+
+///
+/// Same as ``cpu_sample!`` but ends the sample when the enclosing scope ends, just like
+/// ``RemoteryScope``.
+///
+/// # Examples
+///
+/// ```ignore
+/// fn update() {
+///     scoped_cpu_sample!("update");
+///     // some code to profile here
+/// }
+/// ```
+///
+#[macro_export]
+macro_rules! scoped_cpu_sample {
+    ($name:expr) => {
+        $crate::scoped_cpu_sample!($name, $crate::SampleFlags::Default)
+    };
+    ($name:expr, $flags:expr) => {
+        let _rmt_scope = {
+            static RMT_SAMPLE_HASH: ::std::sync::atomic::AtomicU32 = ::std::sync::atomic::AtomicU32::new(0);
+            static RMT_SAMPLE_NAME: &::std::ffi::CStr = $crate::__sample_name!($name);
+            $crate::RemoteryScope::new_cached(RMT_SAMPLE_NAME, $flags, &RMT_SAMPLE_HASH)
+        };
+    };
+}
+
+// Expanded by #[profile] without a name, names the sample after the enclosing function which for
+// methods includes the Self type. That is only known once compiled so the name is built on the
+// first call.
+#[cfg(feature = "enabled")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __scoped_fn_sample {
+    ($flags:expr) => {
+        let _rmt_scope = {
+            fn __rmt_fn() {}
+            static RMT_SAMPLE_HASH: ::std::sync::atomic::AtomicU32 = ::std::sync::atomic::AtomicU32::new(0);
+            static RMT_SAMPLE_NAME: ::std::sync::OnceLock<::std::ffi::CString> = ::std::sync::OnceLock::new();
+            let name = RMT_SAMPLE_NAME.get_or_init(|| $crate::__function_name(__rmt_fn));
+            $crate::RemoteryScope::new_cached(name, $flags, &RMT_SAMPLE_HASH)
+        };
+    };
+}
+
+// Without the enabled feature there is no sample to name so nothing is built at all
+#[cfg(not(feature = "enabled"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __scoped_fn_sample {
+    ($flags:expr) => {};
+}
+
+// Builds the nul-terminated name at compile time, names with nul bytes in them fails to compile
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __sample_name {
+    ($name:expr) => {
+        match ::std::ffi::CStr::from_bytes_with_nul(concat!($name, "\0").as_bytes()) {
+            Ok(name) => name,
+            Err(_) => panic!("sample names can't contain nul bytes"),
+        }
+    };
+}
@@ -0,0 +1,174 @@
+use std::alloc::{self, GlobalAlloc, Layout};
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+#[cfg(feature = "enabled")]
+use std::os::raw::{c_uint, c_void};
+#[cfg(feature = "enabled")]
+use std::ptr;
+
+// Remotery only passes the pointer back on realloc/free so the size of each allocation is stored
+// in a header in front of the memory handed out to the C side. The header is as large as the
+// alignment to keep the returned pointer aligned the same way as malloc would.
+const ALIGN: usize = 16;
+const HEADER_SIZE: usize = ALIGN;
+
+/// Forwards to the ``#[global_allocator]`` of the program
+struct GlobalAllocator;
+
+unsafe impl GlobalAlloc for GlobalAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        alloc::alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        alloc::dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        alloc::realloc(ptr, layout, new_size)
+    }
+}
+
+/// Allocator that Remotery should use for all its internal allocations
+#[derive(Clone)]
+pub(crate) struct Allocator {
+    // Only used by the C callbacks, which aren't built without the ``enabled`` feature
+    #[cfg_attr(not(feature = "enabled"), allow(dead_code))]
+    inner: Arc<dyn GlobalAlloc + Send + Sync>,
+}
+
+impl Allocator {
+    /// Uses the global allocator of the program
+    pub fn global() -> Allocator {
+        Allocator::new(GlobalAllocator)
+    }
+
+    /// Uses ``allocator`` for all allocations done by Remotery
+    pub fn new<A: GlobalAlloc + Send + Sync + 'static>(allocator: A) -> Allocator {
+        Allocator {
+            inner: Arc::new(allocator),
+        }
+    }
+}
+
+impl fmt::Debug for Allocator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Allocator")
+    }
+}
+
+#[cfg(feature = "enabled")]
+fn layout_for(size: usize) -> Option<Layout> {
+    size.checked_add(HEADER_SIZE).and_then(|size| Layout::from_size_align(size, ALIGN).ok())
+}
+
+#[cfg(feature = "enabled")]
+unsafe fn header_of(ptr: *mut c_void) -> *mut u8 {
+    (ptr as *mut u8).sub(HEADER_SIZE)
+}
+
+#[cfg(feature = "enabled")]
+unsafe fn finish_alloc(base: *mut u8, size: usize) -> *mut c_void {
+    if base.is_null() {
+        return ptr::null_mut();
+    }
+
+    *(base as *mut usize) = size;
+    base.add(HEADER_SIZE) as *mut c_void
+}
+
+#[cfg(feature = "enabled")]
+pub unsafe extern "C" fn malloc_callback(mm_context: *mut c_void, size: c_uint) -> *mut c_void {
+    let allocator = &*(mm_context as *const Allocator);
+
+    match layout_for(size as usize) {
+        Some(layout) => finish_alloc(allocator.inner.alloc(layout), size as usize),
+        None => ptr::null_mut(),
+    }
+}
+
+#[cfg(feature = "enabled")]
+pub unsafe extern "C" fn realloc_callback(mm_context: *mut c_void, ptr: *mut c_void, size: c_uint) -> *mut c_void {
+    if ptr.is_null() {
+        return malloc_callback(mm_context, size);
+    }
+
+    let allocator = &*(mm_context as *const Allocator);
+    let base = header_of(ptr);
+    let old_size = *(base as *const usize);
+
+    match (layout_for(old_size), layout_for(size as usize)) {
+        (Some(layout), Some(new_layout)) => {
+            finish_alloc(allocator.inner.realloc(base, layout, new_layout.size()), size as usize)
+        }
+        _ => ptr::null_mut(),
+    }
+}
+
+#[cfg(feature = "enabled")]
+pub unsafe extern "C" fn free_callback(mm_context: *mut c_void, ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+
+    let allocator = &*(mm_context as *const Allocator);
+    let base = header_of(ptr);
+    let size = *(base as *const usize);
+
+    if let Some(layout) = layout_for(size) {
+        allocator.inner.dealloc(base, layout);
+    }
+}
+
+// The header needs to be able to hold the size
+const _: () = assert!(mem::size_of::<usize>() <= HEADER_SIZE);
+
+#[cfg(all(test, feature = "enabled"))]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicIsize, Ordering};
+
+    struct CountingAlloc(Arc<AtomicIsize>);
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            self.0.fetch_add(layout.size() as isize, Ordering::SeqCst);
+            alloc::alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            self.0.fetch_sub(layout.size() as isize, Ordering::SeqCst);
+            alloc::dealloc(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn test_alloc_realloc_free() {
+        let in_use = Arc::new(AtomicIsize::new(0));
+        let allocator = Allocator::new(CountingAlloc(in_use.clone()));
+        let context = &allocator as *const Allocator as *mut c_void;
+
+        unsafe {
+            let ptr = malloc_callback(context, 100) as *mut u8;
+            assert_eq!(ptr as usize % ALIGN, 0);
+            assert_eq!(in_use.load(Ordering::SeqCst), (100 + HEADER_SIZE) as isize);
+
+            for i in 0..100 {
+                *ptr.add(i) = i as u8;
+            }
+
+            let ptr = realloc_callback(context, ptr as *mut c_void, 1000) as *mut u8;
+            assert_eq!(in_use.load(Ordering::SeqCst), (1000 + HEADER_SIZE) as isize);
+
+            for i in 0..100 {
+                assert_eq!(*ptr.add(i), i as u8);
+            }
+
+            free_callback(context, ptr as *mut c_void);
+            free_callback(context, ptr::null_mut());
+        }
+
+        assert_eq!(in_use.load(Ordering::SeqCst), 0);
+    }
+}
@@ -50,12 +50,17 @@ pub enum RmtError {
     RMT_ERROR_D3D11_FAILED_TO_CREATE_QUERY = 41,
     RMT_ERROR_OPENGL_ERROR = 42,
     RMT_ERROR_CUDA_UNKNOWN = 43,
+    RMT_ERROR_WEBSOCKET_HANDSHAKE_BAD_TOKEN = 44,
 }
 
 pub type rmtMallocPtr = Option<unsafe extern "C" fn(mm_context: *mut c_void, size: c_uint) -> *mut c_void>;
 pub type rmtReallocPtr = Option<unsafe extern "C" fn(mm_context: *mut c_void, ptr: *mut c_void, size: c_uint) -> *mut c_void>;
 pub type rmtFreePtr = Option<unsafe extern "C" fn(mm_context: *mut c_void, ptr: *mut c_void)>;
 pub type rmtInputHandlerPtr = Option<unsafe extern "C" fn(text: *const c_char, context: *mut c_void)>;
+pub type rmtLogHandlerPtr = Option<unsafe extern "C" fn(context: *mut c_void, thread_name: *const c_char, message: *const c_char, length: c_uint)>;
+pub type rmtSampleTreeHandlerPtr = Option<unsafe extern "C" fn(context: *mut c_void, sample_tree: *mut c_void)>;
+pub type rmtHttpHandlerPtr = Option<unsafe extern "C" fn(context: *mut c_void, path: *const c_char, path_length: c_uint, content_type: *mut *const c_char, data: *mut *const c_void, length: *mut c_uint) -> c_uint>;
+pub type rmtFrameHandlerPtr = Option<unsafe extern "C" fn(context: *mut c_void, frame_index: c_uint, us_start: u64, us_length: u64)>;
 
 #[repr(C)]
 #[derive(Copy, Clone)]
@@ -73,6 +78,18 @@ pub struct RmtSettings {
     pub input_handler: rmtInputHandlerPtr,
     pub input_handler_context: *mut c_void,
     pub logFilename: *const c_char,
+    pub sampletree_handler: rmtSampleTreeHandlerPtr,
+    pub sampletree_context: *mut c_void,
+    pub loghandler: rmtLogHandlerPtr,
+    pub loghandler_context: *mut c_void,
+    pub http_handler: rmtHttpHandlerPtr,
+    pub http_handler_context: *mut c_void,
+    pub maxNbClients: c_uint,
+    pub bind_address: *const c_char,
+    pub bind_dual_stack: c_uint,
+    pub access_token: *const c_char,
+    pub frame_handler: rmtFrameHandlerPtr,
+    pub frame_handler_context: *mut c_void,
 }
 
 extern "C" {
@@ -81,8 +98,23 @@ extern "C" {
     pub fn _rmt_DestroyGlobalInstance(remotery: *mut c_void);
     pub fn _rmt_SetGlobalInstance(remotery: *mut c_void);
     pub fn _rmt_GetGlobalInstance() -> *mut c_void;
+    pub fn _rmt_GetServerAddress(remotery: *mut c_void, host: *mut c_char, host_size: c_uint, port: *mut c_ushort) -> c_uint;
+    pub fn _rmt_SendRawMessage(json: *const c_char, length: c_uint);
+    pub fn _rmt_GetTime() -> u64;
+    pub fn _rmt_MarkFrame() -> c_uint;
+    pub fn _rmt_SendFrame(frame_index: c_uint, us_start: u64, us_length: u64);
     pub fn _rmt_SetCurrentThreadName(thread_name: *const c_char);
     pub fn _rmt_LogText(text: *const c_char);
     pub fn _rmt_BeginCPUSample(name: *const c_char, flags: c_uint, hash_cache: *mut c_uint);
     pub fn _rmt_EndCPUSample();
+    pub fn _rmt_SampleTreeGetThreadName(sample_tree: *mut c_void) -> *const c_char;
+    pub fn _rmt_SampleTreeGetRootSample(sample_tree: *mut c_void) -> *mut c_void;
+    pub fn _rmt_SampleTreeGetFrameIndex(sample_tree: *mut c_void) -> c_uint;
+    pub fn _rmt_SampleGetName(sample: *mut c_void) -> *const c_char;
+    pub fn _rmt_SampleGetNameHash(sample: *mut c_void) -> c_uint;
+    pub fn _rmt_SampleGetID(sample: *mut c_void) -> c_uint;
+    pub fn _rmt_SampleGetStart(sample: *mut c_void) -> u64;
+    pub fn _rmt_SampleGetTime(sample: *mut c_void) -> u64;
+    pub fn _rmt_SampleGetFirstChild(sample: *mut c_void) -> *mut c_void;
+    pub fn _rmt_SampleGetNextSibling(sample: *mut c_void) -> *mut c_void;
 }
@@ -0,0 +1,108 @@
+//! Debug build bookkeeping of the samples that are open on each thread. Remotery keeps a stack of
+//! samples per thread on the C side and ``_rmt_EndCPUSample`` always ends the top one, so ending
+//! samples in the wrong order (or on the wrong thread) silently corrupts the sample tree. This
+//! mirrors the stack on the Rust side to catch that early.
+
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::fmt;
+use std::thread::{self, Thread};
+use Remotery;
+
+/// Name of an open sample, names known at compile time are kept as they are so beginning the
+/// sample doesn't allocate
+#[derive(Clone)]
+pub enum SampleName {
+    Static(&'static CStr),
+    Owned(String),
+}
+
+impl fmt::Display for SampleName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SampleName::Static(name) => f.write_str(&name.to_string_lossy()),
+            SampleName::Owned(ref name) => f.write_str(name),
+        }
+    }
+}
+
+thread_local! {
+    static OPEN_SAMPLES: RefCell<Vec<SampleName>> = const { RefCell::new(Vec::new()) };
+}
+
+// Panics with ``message`` unless the thread already is panicking (as a second panic would abort)
+// in which case it's sent to the viewer console instead.
+fn report(message: String) {
+    if thread::panicking() {
+        Remotery::log_text(&message);
+    } else {
+        panic!("{}", message);
+    }
+}
+
+/// Called for each sample that is begun on the current thread
+pub fn push(name: SampleName) {
+    OPEN_SAMPLES.with(|samples| samples.borrow_mut().push(name));
+}
+
+/// Called for each sample that is ended on the current thread
+pub fn pop() {
+    if OPEN_SAMPLES.with(|samples| samples.borrow_mut().pop()).is_none() {
+        report("Remotery::end_cpu_sample called without an open sample on this thread".to_owned());
+    }
+}
+
+/// The sample opened by a ``RemoteryScope`` and where it sits in the stack
+pub struct OpenSample {
+    name: SampleName,
+    depth: usize,
+    thread: Thread,
+}
+
+impl OpenSample {
+    /// Records the sample named ``name`` that was just begun on the current thread
+    pub fn current(name: SampleName) -> OpenSample {
+        OpenSample {
+            name,
+            depth: OPEN_SAMPLES.with(|samples| samples.borrow().len()),
+            thread: thread::current(),
+        }
+    }
+
+    ///
+    /// Checks that the sample is the top one on the thread that opened it. Returns ``false`` if
+    /// the sample must not be ended as that would end a sample of another thread.
+    ///
+    pub fn check_end(&self) -> bool {
+        let thread = thread::current();
+
+        if thread.id() != self.thread.id() {
+            report(format!("RemoteryScope \"{}\" opened on thread {} ({:?}) was ended on thread {} ({:?})",
+                           self.name,
+                           self.thread.name().unwrap_or("<unnamed>"),
+                           self.thread.id(),
+                           thread.name().unwrap_or("<unnamed>"),
+                           thread.id()));
+            return false;
+        }
+
+        let top = OPEN_SAMPLES.with(|samples| {
+            let samples = samples.borrow();
+
+            if samples.len() > self.depth {
+                Some(samples[samples.len() - 1].clone())
+            } else {
+                None
+            }
+        });
+
+        if let Some(top) = top {
+            report(format!("RemoteryScope \"{}\" ended out of order while \"{}\" is still open", self.name, top));
+        } else if OPEN_SAMPLES.with(|samples| samples.borrow().len()) < self.depth {
+            report(format!("RemoteryScope \"{}\" ended after its sample was already ended", self.name));
+            return false;
+        }
+
+        true
+    }
+}
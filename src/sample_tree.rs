@@ -0,0 +1,185 @@
+//! Completed sample trees received in-process. Each time the outermost sample of a thread ends,
+//! the tree of samples below it is handed over to the Remotery thread. Handlers added with
+//! ``Remotery::add_sample_tree_handler`` (or a channel from ``Remotery::sample_tree_channel``)
+//! get a copy of each tree whether or not a viewer is connected, which allows building overlays,
+//! metrics and recorders without going through the WebSocket.
+
+#[cfg(feature = "enabled")]
+use std::ffi::CStr;
+#[cfg(feature = "enabled")]
+use std::os::raw::{c_char, c_void};
+#[cfg(feature = "enabled")]
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "enabled")]
+use std::sync::Mutex;
+#[cfg(feature = "enabled")]
+use remotery_ffi;
+#[cfg(feature = "enabled")]
+use Remotery;
+
+/// A single sample together with all samples that were begun while it was open
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    /// Name given when the sample was begun
+    pub name: String,
+    /// Hash of the name as calculated by Remotery (not part of the messages sent to the viewer)
+    #[serde(default)]
+    pub name_hash: u32,
+    /// Persistent id of the sample, unique for its position in the tree
+    pub id: u32,
+    /// Start of the sample in microseconds
+    pub us_start: u64,
+    /// Length of the sample in microseconds
+    pub us_length: u64,
+    /// Samples begun while this sample was open, in the order they were begun
+    #[serde(default)]
+    pub children: Vec<Sample>,
+}
+
+/// The samples of one thread from the time its outermost sample began until it ended
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SampleTree {
+    /// Name of the thread the samples were taken on
+    pub thread_name: String,
+    /// The outermost sample
+    pub root: Sample,
+    /// Number of frames marked with ``Remotery::mark_frame`` before the tree completed
+    #[serde(default)]
+    pub frame_index: u32,
+}
+
+impl Sample {
+    /// Length of the sample minus the length of all its children in microseconds
+    pub fn us_self(&self) -> u64 {
+        let children: u64 = self.children.iter().map(|child| child.us_length).sum();
+        self.us_length.saturating_sub(children)
+    }
+
+    /// Calls ``f`` for this sample and all samples below it, parents before children
+    pub fn visit<F: FnMut(&Sample)>(&self, f: &mut F) {
+        f(self);
+
+        for child in &self.children {
+            child.visit(f);
+        }
+    }
+}
+
+#[cfg(feature = "enabled")]
+unsafe fn c_str(text: *const c_char) -> String {
+    if text.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(text).to_string_lossy().into_owned()
+    }
+}
+
+#[cfg(feature = "enabled")]
+unsafe fn copy_sample(sample: *mut c_void) -> Sample {
+    let mut children = Vec::new();
+    let mut child = remotery_ffi::_rmt_SampleGetFirstChild(sample);
+
+    while !child.is_null() {
+        children.push(copy_sample(child));
+        child = remotery_ffi::_rmt_SampleGetNextSibling(child);
+    }
+
+    Sample {
+        name: c_str(remotery_ffi::_rmt_SampleGetName(sample)),
+        name_hash: remotery_ffi::_rmt_SampleGetNameHash(sample),
+        id: remotery_ffi::_rmt_SampleGetID(sample),
+        us_start: remotery_ffi::_rmt_SampleGetStart(sample),
+        us_length: remotery_ffi::_rmt_SampleGetTime(sample),
+        children,
+    }
+}
+
+#[cfg(feature = "enabled")]
+pub(crate) type SampleTreeHandlerFn = Box<dyn Fn(&SampleTree) + Send + Sync>;
+
+/// Owned by the ``Remotery`` instance and handed to the C side as ``sampletree_context``
+#[cfg(feature = "enabled")]
+pub(crate) struct SampleTreeHandlers {
+    handlers: Mutex<Vec<SampleTreeHandlerFn>>,
+}
+
+#[cfg(feature = "enabled")]
+impl SampleTreeHandlers {
+    pub fn new() -> SampleTreeHandlers {
+        SampleTreeHandlers {
+            handlers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn add(&self, handler: SampleTreeHandlerFn) {
+        self.handlers.lock().unwrap_or_else(|e| e.into_inner()).push(handler);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.handlers.lock().unwrap_or_else(|e| e.into_inner()).is_empty()
+    }
+
+    fn call(&self, tree: &SampleTree) {
+        for handler in self.handlers.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            handler(tree);
+        }
+    }
+}
+
+///
+/// Called by the Remotery thread for each completed sample tree. The tree is only valid during
+/// the call so it's copied before being passed on. Panics must not unwind into the C code so they
+/// are caught here and reported to the viewer.
+///
+#[cfg(feature = "enabled")]
+pub(crate) unsafe extern "C" fn sample_tree_callback(context: *mut c_void, sample_tree: *mut c_void) {
+    if context.is_null() || sample_tree.is_null() {
+        return;
+    }
+
+    let handlers = &*(context as *const SampleTreeHandlers);
+    let root = remotery_ffi::_rmt_SampleTreeGetRootSample(sample_tree);
+
+    // The callback is always installed so trees are only copied once there is a handler
+    if root.is_null() || handlers.is_empty() {
+        return;
+    }
+
+    let tree = SampleTree {
+        thread_name: c_str(remotery_ffi::_rmt_SampleTreeGetThreadName(sample_tree)),
+        root: copy_sample(root),
+        frame_index: remotery_ffi::_rmt_SampleTreeGetFrameIndex(sample_tree),
+    };
+
+    if panic::catch_unwind(AssertUnwindSafe(|| handlers.call(&tree))).is_err() {
+        Remotery::log_text("Remotery sample tree handler panicked");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(name: &str, us_length: u64, children: Vec<Sample>) -> Sample {
+        Sample {
+            name: name.to_owned(),
+            name_hash: 0,
+            id: 0,
+            us_start: 0,
+            us_length,
+            children,
+        }
+    }
+
+    #[test]
+    fn test_us_self_and_visit() {
+        let root = sample("frame", 100, vec![sample("update", 30, vec![]), sample("render", 50, vec![sample("draw", 60, vec![])])]);
+
+        assert_eq!(root.us_self(), 20);
+        assert_eq!(root.children[1].us_self(), 0);
+
+        let mut names = Vec::new();
+        root.visit(&mut |s| names.push(s.name.clone()));
+        assert_eq!(names, ["frame", "update", "render", "draw"]);
+    }
+}
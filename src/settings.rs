@@ -0,0 +1,439 @@
+use std::alloc::GlobalAlloc;
+use std::net::IpAddr;
+use std::time::Duration;
+use capture::CaptureMode;
+use error::RemoteryError;
+use memory::Allocator;
+#[cfg(feature = "enabled")]
+use std::ffi::CString;
+#[cfg(feature = "enabled")]
+use std::net::Ipv4Addr;
+#[cfg(feature = "enabled")]
+use std::os::raw::{c_char, c_void};
+#[cfg(feature = "enabled")]
+use std::ptr;
+#[cfg(feature = "enabled")]
+use memory;
+#[cfg(feature = "enabled")]
+use thread;
+#[cfg(feature = "enabled")]
+use viewer;
+#[cfg(feature = "enabled")]
+use remotery_ffi::{RmtSettings, rmtMallocPtr, rmtReallocPtr, rmtFreePtr};
+
+/// Settings used when creating the global Remotery instance. Start from ``RemoterySettings::new``
+/// and change the values that needs to be changed. The settings are validated and copied over to
+/// the C side by ``Remotery::create_global_instance_with``
+///
+/// The settings that map to ``rmtSettings`` default to the same values as in the C lib. These
+/// defaults differ or only exist on the Rust side:
+///
+/// * ``serve_viewer``: ``true``, the C lib doesn't serve the viewer over HTTP
+/// * ``max_clients``: ``4``, upstream Remotery only accepts a single viewer
+/// * ``capture_mode``: ``CaptureMode::Viewer``
+/// * ``auto_thread_names``: ``false``
+/// * ``counter_interval``: 100 ms
+/// * ``frame_history``: 300 frames
+///
+/// # Examples
+///
+/// ```ignore
+/// let settings = RemoterySettings::new()
+///     .port(17816)
+///     .limit_connections_to_localhost(true)
+///     .log_filename("my_log.txt");
+///
+/// let _remotery = Remotery::create_global_instance_with(settings).unwrap();
+/// ```
+///
+#[derive(Clone, Debug)]
+pub struct RemoterySettings {
+    port: u16,
+    limit_connections_to_localhost: bool,
+    ms_sleep_between_server_updates: u32,
+    message_queue_size_in_bytes: u32,
+    max_nb_messages_per_update: u32,
+    log_filename: String,
+    allocator: Option<Allocator>,
+    auto_thread_names: bool,
+    capture_mode: CaptureMode,
+    serve_viewer: bool,
+    max_clients: u32,
+    bind_address: Option<IpAddr>,
+    dual_stack: bool,
+    access_token: Option<String>,
+    counter_interval: Option<Duration>,
+    frame_history: usize,
+}
+
+impl Default for RemoterySettings {
+    fn default() -> RemoterySettings {
+        RemoterySettings {
+            port: 0x4597,
+            limit_connections_to_localhost: false,
+            ms_sleep_between_server_updates: 10,
+            message_queue_size_in_bytes: 64 * 1024,
+            max_nb_messages_per_update: 100,
+            log_filename: "rmtLog.txt".to_owned(),
+            allocator: None,
+            auto_thread_names: false,
+            capture_mode: CaptureMode::Viewer,
+            serve_viewer: true,
+            max_clients: 4,
+            bind_address: None,
+            dual_stack: false,
+            access_token: None,
+            counter_interval: Some(Duration::from_millis(100)),
+            frame_history: 300,
+        }
+    }
+}
+
+impl RemoterySettings {
+    /// Creates settings with the same default values as the C lib uses
+    pub fn new() -> RemoterySettings {
+        RemoterySettings::default()
+    }
+
+    /// Which port to listen for incoming connections on. Use 0 to have the OS pick a free port
+    /// and ``Remotery::local_addr`` to find out which one it was.
+    pub fn port(mut self, port: u16) -> RemoterySettings {
+        self.port = port;
+        self
+    }
+
+    /// Only allow connections on localhost. For dev builds you may want to access your program
+    /// from other devices but if you distribute it with Remotery active it's probably best
+    /// to limit connections to localhost.
+    pub fn limit_connections_to_localhost(mut self, limit: bool) -> RemoterySettings {
+        self.limit_connections_to_localhost = limit;
+        self
+    }
+
+    ///
+    /// Listens on a specific interface (such as ``192.168.1.10``) or on all IPv6 interfaces with
+    /// ``::``. Overrides ``limit_connections_to_localhost`` which only picks between ``0.0.0.0``
+    /// and ``127.0.0.1``.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let settings = RemoterySettings::new()
+    ///     .bind_address(Ipv6Addr::UNSPECIFIED.into())
+    ///     .dual_stack(true)
+    ///     .port(0);
+    /// ```
+    ///
+    pub fn bind_address(mut self, address: IpAddr) -> RemoterySettings {
+        self.bind_address = Some(address);
+        self
+    }
+
+    /// Also accept IPv4 connections when listening on an IPv6 ``bind_address``. Off by default
+    /// so IPv6 addresses behave the same on all platforms.
+    pub fn dual_stack(mut self, enabled: bool) -> RemoterySettings {
+        self.dual_stack = enabled;
+        self
+    }
+
+    ///
+    /// Requires viewers to pass ``token`` when connecting, either as a ``token`` query parameter
+    /// (``ws://host:port/?token=...``) or an ``Authorization: Bearer`` header. Connections without
+    /// it are rejected and can't send console input. Recommended whenever
+    /// ``limit_connections_to_localhost`` is off. The token must be 1 to 255 characters of
+    /// ``A-Z``, ``a-z``, ``0-9``, ``-``, ``.``, ``_`` and ``~`` so it can be used in a URL as is.
+    ///
+    pub fn access_token<S: Into<String>>(mut self, token: S) -> RemoterySettings {
+        self.access_token = Some(token.into());
+        self
+    }
+
+    ///
+    /// How often a snapshot of all counters and gauges is sent to the viewers, see the
+    /// ``counters`` module. ``Duration::ZERO`` turns off the timer so snapshots are only sent by
+    /// ``Remotery::send_counters``. Defaults to 100 ms.
+    ///
+    pub fn counter_interval(mut self, interval: Duration) -> RemoterySettings {
+        self.counter_interval = if interval.is_zero() { None } else { Some(interval) };
+        self
+    }
+
+    /// How many of the most recent frames ``Remotery::frame_stats`` is calculated over, see the
+    /// ``frames`` module. Must be non-zero, defaults to 300.
+    pub fn frame_history(mut self, frames: usize) -> RemoterySettings {
+        self.frame_history = frames;
+        self
+    }
+
+    /// How long to sleep (in ms) between server updates, hopefully trying to give a little CPU
+    /// back to other threads.
+    pub fn ms_sleep_between_server_updates(mut self, ms: u32) -> RemoterySettings {
+        self.ms_sleep_between_server_updates = ms;
+        self
+    }
+
+    /// Size of the internal message queues Remotery uses. Will be rounded to page granularity
+    /// of 64k. Must be non-zero.
+    pub fn message_queue_size_in_bytes(mut self, size: u32) -> RemoterySettings {
+        self.message_queue_size_in_bytes = size;
+        self
+    }
+
+    /// Upper limit of how many messages the server consumes per update. If this is too high the
+    /// server network code may not get a chance to update when the queue is continuously pushed to.
+    /// Must be non-zero.
+    pub fn max_nb_messages_per_update(mut self, count: u32) -> RemoterySettings {
+        self.max_nb_messages_per_update = count;
+        self
+    }
+
+    /// How many viewers can be connected at the same time. Every sample tree and log line is sent
+    /// to all of them, a viewer that doesn't keep up with reading them misses messages and is only
+    /// disconnected once it has been stalled for a while. Must be non-zero.
+    pub fn max_clients(mut self, count: u32) -> RemoterySettings {
+        self.max_clients = count;
+        self
+    }
+
+    /// Name of the log file. Must not contain any nul bytes.
+    pub fn log_filename<S: Into<String>>(mut self, filename: S) -> RemoterySettings {
+        self.log_filename = filename.into();
+        self
+    }
+
+    /// Routes all allocations done by Remotery through the ``#[global_allocator]`` of the program
+    /// instead of the C runtime ``malloc``/``realloc``/``free``.
+    pub fn use_global_allocator(mut self) -> RemoterySettings {
+        self.allocator = Some(Allocator::global());
+        self
+    }
+
+    ///
+    /// Routes all allocations done by Remotery through ``allocator``. The size and alignment
+    /// needed to call ``dealloc`` and ``realloc`` are tracked for each allocation so ``allocator``
+    /// will always get the same ``Layout`` back as the memory was allocated with.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// let settings = RemoterySettings::new().allocator(std::alloc::System);
+    /// ```
+    ///
+    pub fn allocator<A: GlobalAlloc + Send + Sync + 'static>(mut self, allocator: A) -> RemoterySettings {
+        self.allocator = Some(Allocator::new(allocator));
+        self
+    }
+
+    ///
+    /// Names each thread after its ``std::thread`` name (such as ``main``) the first time it
+    /// begins a sample, unless ``Remotery::set_current_thread_name`` was already called on it.
+    ///
+    pub fn auto_thread_names(mut self, enabled: bool) -> RemoterySettings {
+        self.auto_thread_names = enabled;
+        self
+    }
+
+    ///
+    /// Sets where profiling data goes besides a connected viewer. With ``CaptureMode::File`` all
+    /// sample trees and log lines are written to a capture file even if no viewer ever connects,
+    /// see the ``capture`` module for the format.
+    ///
+    pub fn capture_mode(mut self, mode: CaptureMode) -> RemoterySettings {
+        self.capture_mode = mode;
+        self
+    }
+
+    ///
+    /// Serves the web viewer over plain HTTP on the same port, so it can be opened with
+    /// ``http://host:port/`` without a separate copy of ``index.html``. On by default.
+    ///
+    pub fn serve_viewer(mut self, serve: bool) -> RemoterySettings {
+        self.serve_viewer = serve;
+        self
+    }
+
+    // The address the server listens on, the port is only known once it's running
+    #[cfg(feature = "enabled")]
+    pub(crate) fn get_bind_ip(&self) -> IpAddr {
+        match self.bind_address {
+            Some(address) => address,
+            None if self.limit_connections_to_localhost => Ipv4Addr::LOCALHOST.into(),
+            None => Ipv4Addr::UNSPECIFIED.into(),
+        }
+    }
+
+    #[cfg(feature = "enabled")]
+    pub(crate) fn get_counter_interval(&self) -> Option<Duration> {
+        self.counter_interval
+    }
+
+    #[cfg(feature = "enabled")]
+    pub(crate) fn get_frame_history(&self) -> usize {
+        self.frame_history
+    }
+
+    #[cfg(feature = "enabled")]
+    pub(crate) fn get_capture_mode(&self) -> &CaptureMode {
+        &self.capture_mode
+    }
+
+    /// Checks that all values can be handed over to the C side.
+    pub fn validate(&self) -> Result<(), RemoteryError> {
+        if self.message_queue_size_in_bytes == 0 ||
+           self.max_nb_messages_per_update == 0 ||
+           self.max_clients == 0 ||
+           self.frame_history == 0 ||
+           self.log_filename.as_bytes().contains(&0) {
+            return Err(RemoteryError::InvalidSettings);
+        }
+
+        if let Some(ref token) = self.access_token {
+            let url_safe = |c: char| c.is_ascii_alphanumeric() || "-._~".contains(c);
+
+            if token.is_empty() || token.len() > 255 || !token.chars().all(url_safe) {
+                return Err(RemoteryError::InvalidSettings);
+            }
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Writes the settings to the C settings struct. The returned value owns the strings that the
+    /// C side points to and needs to be kept alive for as long as the settings are being used.
+    ///
+    #[cfg(feature = "enabled")]
+    pub(crate) unsafe fn apply(&self, settings: *mut RmtSettings) -> Result<AppliedSettings, RemoteryError> {
+        self.validate()?;
+
+        let log_filename = CString::new(self.log_filename.as_bytes()).map_err(|_| RemoteryError::InvalidSettings)?;
+        let bind_address = match self.bind_address {
+            Some(address) => Some(CString::new(address.to_string()).map_err(|_| RemoteryError::InvalidSettings)?),
+            None => None,
+        };
+        let access_token = match self.access_token {
+            Some(ref token) => Some(CString::new(token.as_bytes()).map_err(|_| RemoteryError::InvalidSettings)?),
+            None => None,
+        };
+        let applied = AppliedSettings {
+            settings,
+            prev_log_filename: (*settings).logFilename,
+            log_filename,
+            bind_address,
+            access_token,
+            prev_malloc: (*settings).malloc,
+            prev_realloc: (*settings).realloc,
+            prev_free: (*settings).free,
+            prev_mm_context: (*settings).mm_context,
+            allocator: self.allocator.clone().map(Box::new),
+        };
+
+        let settings = &mut *settings;
+
+        if let Some(ref allocator) = applied.allocator {
+            settings.malloc = Some(memory::malloc_callback);
+            settings.realloc = Some(memory::realloc_callback);
+            settings.free = Some(memory::free_callback);
+            settings.mm_context = &**allocator as *const Allocator as *mut c_void;
+        }
+
+        settings.port = self.port;
+        settings.limit_connections_to_localhost = self.limit_connections_to_localhost as u32;
+        settings.msSleepBetweenServerUpdates = self.ms_sleep_between_server_updates;
+        settings.messageQueueSizeInBytes = self.message_queue_size_in_bytes;
+        settings.maxNbMessagesPerUpdate = self.max_nb_messages_per_update;
+        settings.maxNbClients = self.max_clients;
+        settings.bind_address = applied.bind_address.as_ref().map_or(ptr::null(), |address| address.as_ptr());
+        settings.bind_dual_stack = self.dual_stack as u32;
+        settings.access_token = applied.access_token.as_ref().map_or(ptr::null(), |token| token.as_ptr());
+        settings.logFilename = applied.log_filename.as_ptr();
+        settings.http_handler = if self.serve_viewer { Some(viewer::http_callback) } else { None };
+
+        thread::set_auto_names(self.auto_thread_names);
+
+        Ok(applied)
+    }
+}
+
+/// Keeps the strings and allocator referenced by the C settings alive and restores the previous
+/// pointers once dropped so the C side never sees a dangling pointer.
+#[cfg(feature = "enabled")]
+pub(crate) struct AppliedSettings {
+    settings: *mut RmtSettings,
+    prev_log_filename: *const c_char,
+    log_filename: CString,
+    bind_address: Option<CString>,
+    access_token: Option<CString>,
+    prev_malloc: rmtMallocPtr,
+    prev_realloc: rmtReallocPtr,
+    prev_free: rmtFreePtr,
+    prev_mm_context: *mut c_void,
+    allocator: Option<Box<Allocator>>,
+}
+
+#[cfg(feature = "enabled")]
+impl Drop for AppliedSettings {
+    fn drop(&mut self) {
+        unsafe {
+            let settings = &mut *self.settings;
+
+            if settings.logFilename == self.log_filename.as_ptr() {
+                settings.logFilename = self.prev_log_filename;
+            }
+
+            if self.allocator.is_some() {
+                settings.malloc = self.prev_malloc;
+                settings.realloc = self.prev_realloc;
+                settings.free = self.prev_free;
+                settings.mm_context = self.prev_mm_context;
+            }
+
+            settings.http_handler = None;
+            settings.bind_address = ptr::null();
+            settings.access_token = ptr::null();
+        }
+
+        thread::set_auto_names(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_are_valid() {
+        assert!(RemoterySettings::new().validate().is_ok());
+    }
+
+    #[test]
+    fn test_zero_sizes_are_rejected() {
+        assert!(RemoterySettings::new().message_queue_size_in_bytes(0).validate().is_err());
+        assert!(RemoterySettings::new().max_nb_messages_per_update(0).validate().is_err());
+        assert!(RemoterySettings::new().max_clients(0).validate().is_err());
+        assert!(RemoterySettings::new().frame_history(0).validate().is_err());
+    }
+
+    #[test]
+    fn test_access_token_must_be_url_safe() {
+        assert!(RemoterySettings::new().access_token("s3cret-T0ken_1.2~").validate().is_ok());
+        assert!(RemoterySettings::new().access_token("").validate().is_err());
+        assert!(RemoterySettings::new().access_token("a b").validate().is_err());
+        assert!(RemoterySettings::new().access_token("a&token=b").validate().is_err());
+        assert!(RemoterySettings::new().access_token("x".repeat(256)).validate().is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "enabled")]
+    fn test_bind_ip() {
+        assert_eq!(RemoterySettings::new().get_bind_ip().to_string(), "0.0.0.0");
+        assert_eq!(RemoterySettings::new().limit_connections_to_localhost(true).get_bind_ip().to_string(), "127.0.0.1");
+        assert_eq!(RemoterySettings::new().bind_address("::1".parse().unwrap()).get_bind_ip().to_string(), "::1");
+    }
+
+    #[test]
+    fn test_nul_in_log_filename_is_rejected() {
+        assert!(RemoterySettings::new().log_filename("rmt\0log.txt").validate().is_err());
+        assert!(RemoterySettings::new().log_filename("remotery.log").validate().is_ok());
+    }
+}
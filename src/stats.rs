@@ -0,0 +1,473 @@
+//! Numeric summaries of sample trees. An ``Aggregator`` consumes trees (from
+//! ``Remotery::add_sample_tree_handler``, a capture file or the client) and keeps statistics for
+//! each call path (such as ``frame/update/physics``) and each plain sample name.
+//!
+//! Sample lengths are counted in a histogram per path and name instead of being kept, so memory
+//! stays bounded however long an aggregator runs. Lengths below 128 us are counted exactly and
+//! larger ones in buckets 1/64 of their size apart, which puts the percentiles within 1% of the
+//! exact values.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let aggregator = Arc::new(Mutex::new(Aggregator::new().window(Window::Duration(Duration::from_secs(5)))));
+//! let feed = aggregator.clone();
+//!
+//! remotery.add_sample_tree_handler(move |tree| feed.lock().unwrap().add_tree(tree));
+//!
+//! // ... later
+//! if let Some(report) = aggregator.lock().unwrap().completed_window() {
+//!     print!("{}", stats::format_table(&report.names));
+//! }
+//! ```
+//!
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::io::{self, Read};
+use std::time::Duration;
+use capture::{self, Record};
+use sample_tree::{Sample, SampleTree};
+
+/// Separates the sample names of a call path
+pub const PATH_SEPARATOR: char = '/';
+
+/// How long statistics are collected before they are reset
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Window {
+    /// Collect until ``Aggregator::reset`` is called (default)
+    #[default]
+    Unbounded,
+    /// Reset after the given number of ``Aggregator::end_frame`` calls
+    Frames(u32),
+    ///
+    /// Reset once a tree starts the given time after the first tree of the window. Uses the
+    /// sample times so recorded captures are windowed the same way as live data.
+    ///
+    Duration(Duration),
+}
+
+// Each power of two above the exactly counted lengths is split into 2^SUB_BUCKET_BITS buckets
+const SUB_BUCKET_BITS: u32 = 6;
+
+// Lengths below this are counted exactly
+const EXACT_LIMIT: u64 = 2 << SUB_BUCKET_BITS;
+
+// Width of the bucket starting at ``start``, a value returned by ``bucket_start``
+fn bucket_width(start: u64) -> u64 {
+    if start < EXACT_LIMIT {
+        1
+    } else {
+        1 << (63 - start.leading_zeros() - SUB_BUCKET_BITS)
+    }
+}
+
+// Start of the histogram bucket ``us_length`` is counted in
+fn bucket_start(us_length: u64) -> u64 {
+    if us_length < EXACT_LIMIT {
+        us_length
+    } else {
+        us_length & !(bucket_width(us_length) - 1)
+    }
+}
+
+/// Running statistics of one path or name
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Stats {
+    count: u64,
+    total_us: u64,
+    self_us: u64,
+    min_us: u64,
+    max_us: u64,
+    // Number of lengths per bucket start, there are at most a few thousand buckets
+    histogram: BTreeMap<u64, u64>,
+}
+
+impl Stats {
+    #[cfg(feature = "enabled")]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn add(&mut self, us_length: u64, us_self: u64) {
+        if self.count == 0 || us_length < self.min_us {
+            self.min_us = us_length;
+        }
+
+        self.count += 1;
+        self.max_us = self.max_us.max(us_length);
+        self.total_us += us_length;
+        self.self_us += us_self;
+        *self.histogram.entry(bucket_start(us_length)).or_insert(0) += 1;
+    }
+
+    // Nearest-rank percentile, taken as the middle of its bucket
+    fn percentile(&self, p: f64) -> u64 {
+        let rank = ((p / 100.0 * self.count as f64).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+
+        for (&start, &count) in &self.histogram {
+            seen += count;
+
+            if seen >= rank {
+                return (start + bucket_width(start) / 2).clamp(self.min_us, self.max_us);
+            }
+        }
+
+        self.max_us
+    }
+
+    pub fn entry(&self, name: &str) -> Entry {
+        Entry {
+            name: name.to_owned(),
+            count: self.count,
+            total_us: self.total_us,
+            self_us: self.self_us,
+            min_us: self.min_us,
+            max_us: self.max_us,
+            mean_us: self.total_us as f64 / self.count as f64,
+            p50_us: self.percentile(50.0),
+            p90_us: self.percentile(90.0),
+            p99_us: self.percentile(99.0),
+        }
+    }
+}
+
+/// Statistics of one path or name in a ``Report``. All times are in microseconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    /// Call path (names separated by ``/``) or plain sample name
+    pub name: String,
+    /// Number of samples
+    pub count: u64,
+    /// Sum of the lengths of all samples
+    pub total_us: u64,
+    /// Sum of the lengths of all samples minus the time spent in their children
+    pub self_us: u64,
+    /// Length of the shortest sample
+    pub min_us: u64,
+    /// Length of the longest sample
+    pub max_us: u64,
+    /// Mean length of the samples
+    pub mean_us: f64,
+    /// Median length of the samples (within 1%, see the module documentation)
+    pub p50_us: u64,
+    /// 90th percentile of the sample lengths (within 1%)
+    pub p90_us: u64,
+    /// 99th percentile of the sample lengths (within 1%)
+    pub p99_us: u64,
+}
+
+/// What the entries of a ``Report`` are sorted by, largest first
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortBy {
+    /// Total time
+    Total,
+    /// Self time
+    SelfTime,
+    /// Number of samples
+    Count,
+    /// Longest sample
+    Max,
+    /// Mean length
+    Mean,
+    /// 99th percentile
+    P99,
+}
+
+/// Statistics of all paths and names, sorted by total time unless sorted again with ``sort_by``
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    /// Statistics per call path such as ``frame/update/physics``
+    pub paths: Vec<Entry>,
+    /// Statistics per sample name no matter where in the tree the samples are
+    pub names: Vec<Entry>,
+}
+
+impl Report {
+    /// Sorts the entries by ``key`` with the largest first, ties are sorted by name
+    pub fn sort_by(&mut self, key: SortBy) {
+        let compare = |a: &Entry, b: &Entry| {
+            let order = match key {
+                SortBy::Total => b.total_us.cmp(&a.total_us),
+                SortBy::SelfTime => b.self_us.cmp(&a.self_us),
+                SortBy::Count => b.count.cmp(&a.count),
+                SortBy::Max => b.max_us.cmp(&a.max_us),
+                SortBy::Mean => b.mean_us.partial_cmp(&a.mean_us).unwrap_or(Ordering::Equal),
+                SortBy::P99 => b.p99_us.cmp(&a.p99_us),
+            };
+
+            order.then_with(|| a.name.cmp(&b.name))
+        };
+
+        self.paths.sort_by(compare);
+        self.names.sort_by(compare);
+    }
+
+    /// Returns the entry of a call path such as ``frame/update``
+    pub fn path(&self, path: &str) -> Option<&Entry> {
+        self.paths.iter().find(|entry| entry.name == path)
+    }
+
+    /// Returns the entry of a plain sample name
+    pub fn name(&self, name: &str) -> Option<&Entry> {
+        self.names.iter().find(|entry| entry.name == name)
+    }
+}
+
+/// Formats ``entries`` as a table with times in milliseconds
+pub fn format_table(entries: &[Entry]) -> String {
+    let mut table = format!("{:<40} {:>8} {:>11} {:>11} {:>9} {:>9} {:>9} {:>9} {:>9}\n",
+                            "name", "count", "total ms", "self ms", "mean ms", "p50 ms", "p90 ms", "p99 ms", "max ms");
+
+    let ms = |us: u64| us as f64 / 1000.0;
+
+    for entry in entries {
+        let _ = writeln!(table, "{:<40} {:>8} {:>11.3} {:>11.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3}",
+                         entry.name, entry.count, ms(entry.total_us), ms(entry.self_us), entry.mean_us / 1000.0,
+                         ms(entry.p50_us), ms(entry.p90_us), ms(entry.p99_us), ms(entry.max_us));
+    }
+
+    table
+}
+
+/// Collects statistics from sample trees, see the module documentation
+#[derive(Clone, Debug, Default)]
+pub struct Aggregator {
+    window: Window,
+    paths: HashMap<String, Stats>,
+    names: HashMap<String, Stats>,
+    window_frames: u32,
+    window_start_us: Option<u64>,
+    completed: Option<Report>,
+}
+
+impl Aggregator {
+    /// Creates an aggregator that collects until it's reset
+    pub fn new() -> Aggregator {
+        Aggregator::default()
+    }
+
+    /// Sets how long statistics are collected before they are reset
+    pub fn window(mut self, window: Window) -> Aggregator {
+        self.window = window;
+        self
+    }
+
+    /// Reads all sample trees of a capture written with ``CaptureMode::File`` or ``remotery-record``
+    pub fn from_capture<R: Read>(capture: R) -> io::Result<Aggregator> {
+        let mut aggregator = Aggregator::new();
+
+        for record in capture::read(capture) {
+            aggregator.add_record(&record?);
+        }
+
+        Ok(aggregator)
+    }
+
+    /// Adds all samples of ``tree``
+    pub fn add_tree(&mut self, tree: &SampleTree) {
+        if let Window::Duration(duration) = self.window {
+            let start = *self.window_start_us.get_or_insert(tree.root.us_start);
+
+            if tree.root.us_start.saturating_sub(start) >= duration.as_micros() as u64 {
+                self.complete_window();
+                self.window_start_us = Some(tree.root.us_start);
+            }
+        }
+
+        let mut path = String::new();
+        self.add_sample(&tree.root, &mut path);
+    }
+
+    /// Adds the sample trees of a capture record and ends a frame for ``FRAME`` records, other
+    /// records are ignored
+    pub fn add_record(&mut self, record: &Record) {
+        if let Record::Frame { .. } = *record {
+            self.end_frame();
+        }
+
+        for tree in record.sample_trees() {
+            self.add_tree(&tree);
+        }
+    }
+
+    /// Marks the end of a frame, which resets the statistics every ``n`` frames with ``Window::Frames(n)``
+    pub fn end_frame(&mut self) {
+        if let Window::Frames(frames) = self.window {
+            self.window_frames += 1;
+
+            if self.window_frames >= frames {
+                self.complete_window();
+            }
+        }
+    }
+
+    /// Clears all statistics collected so far
+    pub fn reset(&mut self) {
+        self.paths.clear();
+        self.names.clear();
+        self.window_frames = 0;
+        self.window_start_us = None;
+    }
+
+    /// Returns the report of the statistics collected since the last reset
+    pub fn report(&self) -> Report {
+        let mut report = Report {
+            paths: self.paths.iter().map(|(path, stats)| stats.entry(path)).collect(),
+            names: self.names.iter().map(|(name, stats)| stats.entry(name)).collect(),
+        };
+
+        report.sort_by(SortBy::Total);
+        report
+    }
+
+    /// Returns the report of the last window that was completed, if any
+    pub fn completed_window(&self) -> Option<&Report> {
+        self.completed.as_ref()
+    }
+
+    fn complete_window(&mut self) {
+        self.completed = Some(self.report());
+        self.reset();
+    }
+
+    fn add_sample(&mut self, sample: &Sample, path: &mut String) {
+        let parent_len = path.len();
+
+        if !path.is_empty() {
+            path.push(PATH_SEPARATOR);
+        }
+
+        path.push_str(&sample.name);
+
+        let us_self = sample.us_self();
+
+        // Avoid allocating the keys for paths and names that have been seen before
+        match self.paths.get_mut(path.as_str()) {
+            Some(stats) => stats.add(sample.us_length, us_self),
+            None => self.paths.entry(path.clone()).or_default().add(sample.us_length, us_self),
+        }
+
+        match self.names.get_mut(&sample.name) {
+            Some(stats) => stats.add(sample.us_length, us_self),
+            None => self.names.entry(sample.name.clone()).or_default().add(sample.us_length, us_self),
+        }
+
+        for child in &sample.children {
+            self.add_sample(child, path);
+        }
+
+        path.truncate(parent_len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(name: &str, us_start: u64, us_length: u64, children: Vec<Sample>) -> Sample {
+        Sample { name: name.to_owned(), name_hash: 0, id: 0, us_start, us_length, children }
+    }
+
+    fn frame(us_start: u64, update_us: u64) -> SampleTree {
+        SampleTree {
+            thread_name: "main".to_owned(),
+            root: sample("frame", us_start, 100, vec![
+                sample("update", us_start, update_us, vec![sample("alloc", us_start, 5, vec![])]),
+                sample("render", us_start + 50, 40, vec![sample("alloc", us_start + 50, 10, vec![])]),
+            ]),
+            frame_index: 0,
+        }
+    }
+
+    #[test]
+    fn test_paths_and_names() {
+        let mut aggregator = Aggregator::new();
+
+        for i in 1..=10 {
+            aggregator.add_tree(&frame(i * 1000, i * 5));
+        }
+
+        let report = aggregator.report();
+        let frame = report.path("frame").unwrap();
+        assert_eq!((frame.count, frame.total_us, frame.min_us, frame.max_us), (10, 1000, 100, 100));
+        assert_eq!(frame.self_us, 1000 - 275 - 400);
+
+        let update = report.path("frame/update").unwrap();
+        assert_eq!((update.min_us, update.max_us, update.mean_us), (5, 50, 27.5));
+        assert_eq!((update.p50_us, update.p90_us, update.p99_us), (25, 45, 50));
+
+        assert_eq!(report.path("frame/render/alloc").unwrap().total_us, 100);
+        assert_eq!(report.name("alloc").unwrap().total_us, 150);
+        assert_eq!(report.name("alloc").unwrap().count, 20);
+
+        // Sorted by total time
+        assert_eq!(report.names.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), ["frame", "render", "update", "alloc"]);
+    }
+
+    #[test]
+    fn test_histogram_is_bounded_and_accurate() {
+        let mut stats = Stats::default();
+        let lengths: Vec<u64> = (0..200_000u64).map(|i| (i * 7919) % 1_000_000).collect();
+
+        for &length in &lengths {
+            stats.add(length, 0);
+        }
+
+        assert!(stats.histogram.len() < 1024);
+
+        let mut sorted = lengths.clone();
+        sorted.sort_unstable();
+        let entry = stats.entry("spread");
+
+        for &(p, value) in &[(50.0, entry.p50_us), (90.0, entry.p90_us), (99.0, entry.p99_us)] {
+            let exact = sorted[(p / 100.0 * sorted.len() as f64).ceil() as usize - 1];
+            assert!((value as f64 - exact as f64).abs() <= exact as f64 * 0.01, "p{} {} vs {}", p, value, exact);
+        }
+
+        assert_eq!((entry.count, entry.min_us, entry.max_us), (200_000, sorted[0], sorted[sorted.len() - 1]));
+    }
+
+    #[test]
+    fn test_bucket_start() {
+        assert_eq!(bucket_start(127), 127);
+        assert_eq!(bucket_start(128), 128);
+        assert_eq!(bucket_start(129), 128);
+        assert_eq!(bucket_width(128), 2);
+        assert_eq!(bucket_start(1_000_000), 999_424);
+        assert_eq!(bucket_width(999_424), 8192);
+        assert_eq!(bucket_start(u64::MAX), !((1 << 57) - 1));
+    }
+
+    #[test]
+    fn test_windows() {
+        let mut aggregator = Aggregator::new().window(Window::Frames(2));
+
+        for i in 0..3 {
+            aggregator.add_tree(&frame(i * 1000, 10));
+            aggregator.end_frame();
+        }
+
+        assert_eq!(aggregator.completed_window().unwrap().path("frame").unwrap().count, 2);
+        assert_eq!(aggregator.report().path("frame").unwrap().count, 1);
+
+        // Frame records of a capture end frames too
+        let mut aggregator = Aggregator::new().window(Window::Frames(1));
+        aggregator.add_record(&Record::from_sample_tree(&frame(0, 10)));
+        aggregator.add_record(&Record::from_frame(0, 0, 100));
+
+        assert_eq!(aggregator.completed_window().unwrap().path("frame").unwrap().count, 1);
+        assert!(aggregator.report().path("frame").is_none());
+
+        let mut aggregator = Aggregator::new().window(Window::Duration(Duration::from_millis(1)));
+
+        for i in 0..5 {
+            aggregator.add_tree(&frame(i * 400, 10));
+        }
+
+        // Trees at 0, 400 and 800 us form the first window, 1200 starts the next one
+        assert_eq!(aggregator.completed_window().unwrap().path("frame").unwrap().count, 3);
+        assert_eq!(aggregator.report().path("frame").unwrap().count, 2);
+    }
+}
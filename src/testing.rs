@@ -0,0 +1,341 @@
+//! Utilities for checking instrumentation and timings in ``cargo test`` without a viewer.
+//! ``capture`` runs a closure and returns the sample trees recorded on the current thread which
+//! can then be checked with ``assert_sample!`` and ``outline``.
+//!
+//! The first call creates a global Remotery instance that stays alive for the rest of the test
+//! run, so tests using this module must not create their own instance.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate remotery;
+//!
+//! #[test]
+//! fn parse_is_instrumented() {
+//!     let trees = remotery::testing::capture(|| parse("1 + 2"));
+//!
+//!     assert_sample!(trees, "parse/tokenize", count == 1, max < 5ms);
+//!     assert_eq!(remotery::testing::outline(&trees), "parse\n  tokenize\n  build\n");
+//! }
+//! ```
+//!
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use sample_tree::{Sample, SampleTree};
+use stats::{self, Entry};
+use {Remotery, RemoterySettings, SampleFlags};
+
+// Ended on the capturing thread after the closure to know when all its trees have arrived
+const SENTINEL: &str = "remotery::testing::sentinel";
+
+// How long to wait for the trees of a capture before giving up
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Trees received for each capture that is in progress, keyed by the thread name of the capture
+#[derive(Default)]
+struct Captures {
+    trees: Mutex<HashMap<String, Vec<SampleTree>>>,
+    arrived: Condvar,
+}
+
+struct Harness {
+    _remotery: Remotery,
+    captures: Arc<Captures>,
+}
+
+// The instance is only created and never touched again after being stored
+unsafe impl Send for Harness {}
+unsafe impl Sync for Harness {}
+
+static HARNESS: OnceLock<Harness> = OnceLock::new();
+static NEXT_CAPTURE: AtomicUsize = AtomicUsize::new(0);
+
+fn harness() -> &'static Harness {
+    HARNESS.get_or_init(|| {
+        // Any free port so parallel test runs and profiled programs don't collide
+        let settings = RemoterySettings::new()
+            .port(0)
+            .limit_connections_to_localhost(true)
+            .ms_sleep_between_server_updates(1)
+            .message_queue_size_in_bytes(1024 * 1024)
+            .max_nb_messages_per_update(1000);
+
+        let mut remotery = Remotery::create_global_instance_with(settings).unwrap_or_else(|e| {
+            panic!("remotery::testing failed to create Remotery instance: {}", e)
+        });
+
+        let captures = Arc::new(Captures::default());
+        let receiver = captures.clone();
+
+        remotery.add_sample_tree_handler(move |tree| {
+            let mut trees = receiver.trees.lock().unwrap_or_else(|e| e.into_inner());
+
+            if let Some(capture) = trees.get_mut(&tree.thread_name) {
+                capture.push(tree.clone());
+                receiver.arrived.notify_all();
+            }
+        });
+
+        Harness {
+            _remotery: remotery,
+            captures,
+        }
+    })
+}
+
+///
+/// Runs ``f`` and returns the sample trees recorded on the current thread while it ran. The
+/// thread is given a unique Remotery name for the duration of the capture. Must not be called
+/// while a sample is open on the current thread as the trees are only completed once the
+/// outermost sample ends.
+///
+pub fn capture<F: FnOnce()>(f: F) -> Vec<SampleTree> {
+    let captures = &harness().captures;
+    let thread_name = format!("remotery::testing {}", NEXT_CAPTURE.fetch_add(1, Ordering::Relaxed));
+
+    captures.trees.lock().unwrap_or_else(|e| e.into_inner()).insert(thread_name.clone(), Vec::new());
+    Remotery::set_current_thread_name(&thread_name);
+
+    f();
+
+    // Trees from one thread arrive in order so once the sentinel is seen all trees are there
+    Remotery::begin_cpu_sample(SENTINEL, SampleFlags::Default);
+    Remotery::end_cpu_sample();
+
+    let deadline = Instant::now() + TIMEOUT;
+    let mut trees = captures.trees.lock().unwrap_or_else(|e| e.into_inner());
+
+    loop {
+        if trees[&thread_name].last().is_some_and(|tree| tree.root.name == SENTINEL) {
+            break;
+        }
+
+        let now = Instant::now();
+
+        if now >= deadline {
+            trees.remove(&thread_name);
+            panic!("remotery::testing::capture timed out waiting for sample trees");
+        }
+
+        trees = captures.arrived.wait_timeout(trees, deadline - now).unwrap_or_else(|e| e.into_inner()).0;
+    }
+
+    let mut captured = trees.remove(&thread_name).unwrap_or_default();
+    captured.pop();
+    captured
+}
+
+///
+/// Returns the structure of ``trees`` with one sample name per line, indented two spaces per
+/// level, to compare the nesting of samples with ``assert_eq!``.
+///
+pub fn outline(trees: &[SampleTree]) -> String {
+    fn add(outline: &mut String, sample: &Sample, depth: usize) {
+        let _ = writeln!(outline, "{:indent$}{}", "", sample.name, indent = depth * 2);
+
+        for child in &sample.children {
+            add(outline, child, depth + 1);
+        }
+    }
+
+    let mut outline = String::new();
+
+    for tree in trees {
+        add(&mut outline, &tree.root, 0);
+    }
+
+    outline
+}
+
+///
+/// Returns the statistics of all samples in ``trees`` whose call path is ``path`` or ends with
+/// ``/path``, so ``"tokenize"`` matches every sample named tokenize and ``"parse/tokenize"`` only
+/// those directly below a ``parse`` sample. Returns ``None`` if no samples match.
+///
+pub fn stats(trees: &[SampleTree], path: &str) -> Option<Entry> {
+    fn add(stats: &mut stats::Stats, sample: &Sample, current: &mut String, path: &str) {
+        let parent_len = current.len();
+
+        if !current.is_empty() {
+            current.push(stats::PATH_SEPARATOR);
+        }
+
+        current.push_str(&sample.name);
+
+        if current == path || (current.ends_with(path) && current[..current.len() - path.len()].ends_with(stats::PATH_SEPARATOR)) {
+            stats.add(sample.us_length, sample.us_self());
+        }
+
+        for child in &sample.children {
+            add(stats, child, current, path);
+        }
+
+        current.truncate(parent_len);
+    }
+
+    let mut stats = stats::Stats::default();
+
+    for tree in trees {
+        add(&mut stats, &tree.root, &mut String::new(), path);
+    }
+
+    if stats.is_empty() {
+        None
+    } else {
+        Some(stats.entry(path))
+    }
+}
+
+// Parses ``5ms``, ``1.5s`` or ``200us`` to microseconds
+fn parse_time(value: &str) -> Option<f64> {
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let number: f64 = value[..split].parse().ok()?;
+
+    match &value[split..] {
+        "us" => Some(number),
+        "ms" => Some(number * 1000.0),
+        "s" => Some(number * 1000.0 * 1000.0),
+        _ => None,
+    }
+}
+
+///
+/// Checks a single condition of ``assert_sample!`` such as ``max < 5ms``. ``field`` is one of
+/// ``count``, ``total``, ``self``, ``min``, ``max``, ``mean``, ``p50``, ``p90`` and ``p99``.
+/// Times need a unit (``us``, ``ms`` or ``s``) and counts must not have one.
+///
+#[doc(hidden)]
+pub fn check(trees: &[SampleTree], path: &str, field: &str, op: &str, value: &str) -> Result<(), String> {
+    let entry = stats(trees, path);
+
+    let actual = match (field, entry.as_ref()) {
+        ("count", _) => entry.as_ref().map_or(0.0, |e| e.count as f64),
+        (_, None) => return Err(format!("no samples matching \"{}\" in:\n{}", path, outline(trees))),
+        ("total", Some(e)) => e.total_us as f64,
+        ("self", Some(e)) => e.self_us as f64,
+        ("min", Some(e)) => e.min_us as f64,
+        ("max", Some(e)) => e.max_us as f64,
+        ("mean", Some(e)) => e.mean_us,
+        ("p50", Some(e)) => e.p50_us as f64,
+        ("p90", Some(e)) => e.p90_us as f64,
+        ("p99", Some(e)) => e.p99_us as f64,
+        _ => return Err(format!("unknown field \"{}\"", field)),
+    };
+
+    let expected = if field == "count" { value.parse().ok() } else { parse_time(value) };
+    let expected = expected.ok_or_else(|| format!("invalid value \"{}\" for {}", value, field))?;
+
+    let passed = match op {
+        "==" => actual == expected,
+        "!=" => actual != expected,
+        "<" => actual < expected,
+        "<=" => actual <= expected,
+        ">" => actual > expected,
+        ">=" => actual >= expected,
+        _ => return Err(format!("unknown operator \"{}\"", op)),
+    };
+
+    if passed {
+        Ok(())
+    } else if field == "count" {
+        Err(format!("expected count {} {} but it was {}", op, value, actual))
+    } else {
+        Err(format!("expected {} {} {} but it was {}us", field, op, value, actual))
+    }
+}
+
+///
+/// Asserts that samples matching a call path exist in a list of sample trees and optionally
+/// checks their statistics, see ``testing::stats`` for how paths are matched and
+/// ``testing::check`` for the conditions.
+///
+/// # Examples
+///
+/// ```ignore
+/// assert_sample!(trees, "parse/tokenize");
+/// assert_sample!(trees, "parse/tokenize", count == 1, max < 5ms, p99 <= 1.5ms);
+/// ```
+///
+#[macro_export]
+macro_rules! assert_sample {
+    ($trees:expr, $path:expr) => {
+        $crate::assert_sample!($trees, $path, count >= 1)
+    };
+    ($trees:expr, $path:expr, $($field:ident $op:tt $value:tt),+ $(,)*) => {{
+        let trees: &[$crate::sample_tree::SampleTree] = &$trees;
+        $(
+            if let Err(e) = $crate::testing::check(trees, $path, stringify!($field), stringify!($op), stringify!($value)) {
+                panic!("assert_sample!({:?}, {} {} {}) failed: {}", $path, stringify!($field), stringify!($op), stringify!($value), e);
+            }
+        )+
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use RemoteryScope;
+
+    fn sample(name: &str, us_length: u64, children: Vec<Sample>) -> Sample {
+        Sample { name: name.to_owned(), name_hash: 0, id: 0, us_start: 0, us_length, children }
+    }
+
+    fn trees() -> Vec<SampleTree> {
+        vec![SampleTree {
+            thread_name: "main".to_owned(),
+            root: sample("parse", 3000, vec![sample("tokenize", 1000, vec![]), sample("build", 1500, vec![sample("tokenize", 200, vec![])])]),
+            frame_index: 0,
+        }]
+    }
+
+    #[test]
+    fn test_outline_and_paths() {
+        let trees = trees();
+
+        assert_eq!(outline(&trees), "parse\n  tokenize\n  build\n    tokenize\n");
+        assert_eq!(stats(&trees, "tokenize").unwrap().count, 2);
+        assert_eq!(stats(&trees, "parse/tokenize").unwrap().count, 1);
+        assert_eq!(stats(&trees, "build/tokenize").unwrap().max_us, 200);
+        assert!(stats(&trees, "okenize").is_none());
+    }
+
+    #[test]
+    fn test_assert_sample() {
+        let trees = trees();
+
+        assert_sample!(trees, "parse/build");
+        assert_sample!(trees, "tokenize", count == 2, max <= 1ms, total > 1.1ms, self == 1.2ms);
+        assert_sample!(trees, "parse", self == 500us);
+        assert_sample!(trees, "missing", count == 0);
+
+        assert!(check(&trees, "parse", "max", "<", "2ms").unwrap_err().contains("expected max < 2ms but it was 3000us"));
+        assert!(check(&trees, "missing", "max", "<", "2ms").unwrap_err().contains("no samples matching"));
+        assert!(check(&trees, "parse", "max", "<", "2").is_err());
+    }
+
+    #[test]
+    fn test_capture() {
+        let trees = capture(|| {
+            let _parse = RemoteryScope::new("parse", SampleFlags::Default);
+            let _tokenize = RemoteryScope::new("tokenize", SampleFlags::Default);
+            thread::sleep(Duration::from_millis(2));
+        });
+
+        assert_eq!(outline(&trees), "parse\n  tokenize\n");
+        assert_sample!(trees, "parse/tokenize", count == 1, min >= 2ms);
+
+        // Other threads don't show up in the capture
+        let trees = capture(|| {
+            thread::spawn(|| {
+                let _other = RemoteryScope::new("other", SampleFlags::Default);
+            }).join().unwrap();
+        });
+
+        assert!(trees.is_empty());
+    }
+}
@@ -0,0 +1,167 @@
+//! Naming of threads in the Remotery viewer. Threads that never call
+//! ``Remotery::set_current_thread_name`` show up with a generated name, which makes it hard to
+//! tell worker threads apart. The helpers here register the name of the ``std::thread`` instead.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! use remotery::thread::ProfiledThreadBuilder;
+//!
+//! std::thread::Builder::new()
+//!     .name("loader".to_owned())
+//!     .spawn_profiled(|| load_assets())
+//!     .unwrap();
+//!
+//! let pool = rayon::ThreadPoolBuilder::new()
+//!     .thread_name(remotery::thread::rayon_thread_name("rayon"))
+//!     .start_handler(remotery::thread::rayon_start_handler())
+//!     .build()
+//!     .unwrap();
+//!
+//! let runtime = tokio::runtime::Builder::new_multi_thread()
+//!     .thread_name("tokio")
+//!     .on_thread_start(remotery::thread::tokio_on_thread_start())
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+use std::io;
+use std::thread::{self, Builder, JoinHandle};
+use Remotery;
+#[cfg(feature = "enabled")]
+use std::cell::Cell;
+#[cfg(feature = "enabled")]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set from ``RemoterySettings::auto_thread_names`` while the instance is alive
+#[cfg(feature = "enabled")]
+static AUTO_NAMES: AtomicBool = AtomicBool::new(false);
+
+#[cfg(feature = "enabled")]
+thread_local! {
+    static NAMED: Cell<bool> = const { Cell::new(false) };
+}
+
+#[cfg(feature = "enabled")]
+pub(crate) fn set_auto_names(enabled: bool) {
+    AUTO_NAMES.store(enabled, Ordering::Relaxed);
+}
+
+/// Called when a name is set for the current thread so it won't be replaced by the automatic one
+#[cfg(feature = "enabled")]
+pub(crate) fn mark_named() {
+    NAMED.with(|named| named.set(true));
+}
+
+/// Called before each sample is begun, without automatic names this is a single flag test
+#[cfg(feature = "enabled")]
+#[inline(always)]
+pub(crate) fn auto_name_current() {
+    if AUTO_NAMES.load(Ordering::Relaxed) {
+        name_current_once();
+    }
+}
+
+// Names the thread the first time it begins a sample
+#[cfg(feature = "enabled")]
+#[inline(never)]
+fn name_current_once() {
+    if !NAMED.with(|named| named.get()) {
+        mark_named();
+
+        if let Some(name) = thread::current().name() {
+            Remotery::set_current_thread_name(name);
+        }
+    }
+}
+
+///
+/// Registers the name of the current ``std::thread`` with Remotery. Does nothing if the thread
+/// has no name.
+///
+pub fn register_current_thread() {
+    if let Some(name) = thread::current().name() {
+        Remotery::set_current_thread_name(name);
+    }
+}
+
+/// Adds spawning of threads that register their name with Remotery to ``std::thread::Builder``
+pub trait ProfiledThreadBuilder {
+    ///
+    /// Same as ``Builder::spawn`` but calls ``register_current_thread`` in the new thread before
+    /// ``f`` runs so the thread shows up under the name given to the builder.
+    ///
+    fn spawn_profiled<F, T>(self, f: F) -> io::Result<JoinHandle<T>>
+        where F: FnOnce() -> T + Send + 'static, T: Send + 'static;
+}
+
+impl ProfiledThreadBuilder for Builder {
+    fn spawn_profiled<F, T>(self, f: F) -> io::Result<JoinHandle<T>>
+        where F: FnOnce() -> T + Send + 'static, T: Send + 'static
+    {
+        self.spawn(move || {
+            register_current_thread();
+            f()
+        })
+    }
+}
+
+/// Returns a function for ``rayon::ThreadPoolBuilder::thread_name`` that names workers ``prefix-index``
+pub fn rayon_thread_name(prefix: &str) -> impl FnMut(usize) -> String {
+    let prefix = prefix.to_owned();
+    move |index| format!("{}-{}", prefix, index)
+}
+
+///
+/// Returns a function for ``rayon::ThreadPoolBuilder::start_handler`` that registers each worker.
+/// Workers without a ``std::thread`` name are registered as ``rayon-index``.
+///
+pub fn rayon_start_handler() -> impl Fn(usize) + Send + Sync {
+    |index| {
+        match thread::current().name() {
+            Some(name) => Remotery::set_current_thread_name(name),
+            None => Remotery::set_current_thread_name(&format!("rayon-{}", index)),
+        }
+    }
+}
+
+/// Returns a function for ``tokio::runtime::Builder::on_thread_start`` that registers each worker
+pub fn tokio_on_thread_start() -> impl Fn() + Send + Sync {
+    register_current_thread
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rayon_thread_name() {
+        let mut name = rayon_thread_name("pool");
+        assert_eq!(name(0), "pool-0");
+        assert_eq!(name(12), "pool-12");
+    }
+
+    #[test]
+    fn test_spawn_profiled() {
+        let handle = Builder::new()
+            .name("worker".to_owned())
+            .spawn_profiled(|| thread::current().name().map(|name| name.to_owned()))
+            .unwrap();
+
+        assert_eq!(handle.join().unwrap().as_deref(), Some("worker"));
+    }
+
+    #[test]
+    #[cfg(feature = "enabled")]
+    fn test_auto_name_marks_thread() {
+        thread::spawn(|| {
+            auto_name_current();
+            assert!(!NAMED.with(|named| named.get()));
+
+            set_auto_names(true);
+            auto_name_current();
+            set_auto_names(false);
+            assert!(NAMED.with(|named| named.get()));
+        }).join().unwrap();
+    }
+}
@@ -0,0 +1,333 @@
+//! A [tracing-subscriber](https://docs.rs/tracing-subscriber) ``Layer`` that forwards spans and
+//! events to Remotery. Entering a span begins a cpu sample named after the span and exiting it ends
+//! the sample, so code that already is instrumented with ``tracing`` shows up in the viewer without
+//! any extra annotations. Events are sent to the viewer console as ``[LEVEL target] message`` lines.
+//!
+//! Remotery samples have to be ended in the reverse order they were begun, which ``tracing`` doesn't
+//! guarantee for spans. Exiting a span that isn't the innermost one on the thread also ends the
+//! samples of the spans entered inside it (their own exits are then ignored) and is reported to the
+//! viewer console.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! use tracing_subscriber::prelude::*;
+//!
+//! let _remotery = Remotery::create_global_instance().unwrap();
+//!
+//! tracing_subscriber::registry()
+//!     .with(remotery::tracing::RemoteryLayer::new())
+//!     .init();
+//! ```
+//!
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fmt::{self, Write};
+use std::sync::atomic::AtomicU32;
+use std::sync::{Arc, RwLock};
+use tracing_core::callsite::Identifier;
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::{Attributes, Id, Record};
+use tracing_core::{Event, Interest, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+use {Remotery, SampleFlags};
+
+// Name of a span callsite together with the hash cache used for all spans from it
+struct CallsiteName {
+    name: CString,
+    hash_cache: AtomicU32,
+}
+
+// Stored in the extensions of each span to know what sample to begin when it's entered
+enum SpanSample {
+    Callsite(Arc<CallsiteName>),
+    Formatted { name: String, fields: String, sample_name: String },
+}
+
+impl SpanSample {
+    fn begin(&self) {
+        match *self {
+            SpanSample::Callsite(ref callsite) => {
+                Remotery::begin_cpu_sample_cstr(&callsite.name, SampleFlags::Default, &callsite.hash_cache)
+            }
+            SpanSample::Formatted { ref sample_name, .. } => {
+                Remotery::begin_cpu_sample(sample_name, SampleFlags::Default)
+            }
+        }
+    }
+}
+
+thread_local! {
+    // Spans entered on this thread that have an open sample, innermost last
+    static ENTERED: RefCell<Vec<Id>> = const { RefCell::new(Vec::new()) };
+}
+
+fn to_c_name(name: &str) -> CString {
+    CString::new(name.replace('\0', "")).unwrap_or_default()
+}
+
+// Formats the sample name as ``name{field=value other=value}``
+fn format_span_name(name: &str, fields: &str) -> String {
+    if fields.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}{{{}}}", name, fields)
+    }
+}
+
+// Formats the line sent to the viewer for an event
+fn format_event_line(level: &str, target: &str, message: &str, fields: &str) -> String {
+    let mut line = format!("[{} {}] {}", level, target, message);
+
+    if !fields.is_empty() {
+        if !message.is_empty() {
+            line.push(' ');
+        }
+
+        line.push_str(fields);
+    }
+
+    line
+}
+
+// Collects fields as ``name=value`` pairs separated by spaces. The ``message`` field of events
+// is kept separate so it can be put first.
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.record_debug(field, &value)
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+            return;
+        }
+
+        if !self.fields.is_empty() {
+            self.fields.push(' ');
+        }
+
+        let _ = write!(self.fields, "{}={:?}", field.name(), value);
+    }
+}
+
+/// Forwards ``tracing`` spans to Remotery cpu samples and events to the Remotery console
+pub struct RemoteryLayer {
+    span_fields: bool,
+    callsites: RwLock<HashMap<Identifier, Arc<CallsiteName>>>,
+}
+
+impl Default for RemoteryLayer {
+    fn default() -> RemoteryLayer {
+        RemoteryLayer::new()
+    }
+}
+
+impl RemoteryLayer {
+    /// Creates a layer that names samples after the name of the span
+    pub fn new() -> RemoteryLayer {
+        RemoteryLayer {
+            span_fields: false,
+            callsites: RwLock::new(HashMap::new()),
+        }
+    }
+
+    ///
+    /// Includes the fields of spans in the sample name such as ``load{path="level1.map"}``.
+    /// Notice that this makes each span with different field values a separate sample in the
+    /// viewer and the name hash has to be calculated each time the span is entered.
+    ///
+    pub fn with_span_fields(mut self, span_fields: bool) -> RemoteryLayer {
+        self.span_fields = span_fields;
+        self
+    }
+
+    fn callsite_name(&self, metadata: &'static Metadata<'static>) -> Arc<CallsiteName> {
+        let id = metadata.callsite();
+
+        if let Some(name) = self.callsites.read().unwrap_or_else(|e| e.into_inner()).get(&id) {
+            return name.clone();
+        }
+
+        let mut callsites = self.callsites.write().unwrap_or_else(|e| e.into_inner());
+
+        callsites.entry(id).or_insert_with(|| Arc::new(CallsiteName {
+            name: to_c_name(metadata.name()),
+            hash_cache: AtomicU32::new(0),
+        })).clone()
+    }
+}
+
+impl<S> Layer<S> for RemoteryLayer where S: Subscriber + for<'a> LookupSpan<'a> {
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if metadata.is_span() {
+            self.callsite_name(metadata);
+        }
+
+        Interest::always()
+    }
+
+    fn on_new_span(&self, attrs: &Attributes, id: &Id, ctx: Context<S>) {
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => return,
+        };
+
+        let sample = if self.span_fields {
+            let mut visitor = FieldVisitor::default();
+            attrs.record(&mut visitor);
+
+            let name = span.name().to_owned();
+            let sample_name = format_span_name(&name, &visitor.fields);
+            SpanSample::Formatted { name, fields: visitor.fields, sample_name }
+        } else {
+            SpanSample::Callsite(self.callsite_name(span.metadata()))
+        };
+
+        span.extensions_mut().insert(sample);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record, ctx: Context<S>) {
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => return,
+        };
+
+        let mut extensions = span.extensions_mut();
+
+        if let Some(&mut SpanSample::Formatted { ref name, ref mut fields, ref mut sample_name }) = extensions.get_mut::<SpanSample>() {
+            let mut visitor = FieldVisitor {
+                message: String::new(),
+                fields: fields.clone(),
+            };
+            values.record(&mut visitor);
+
+            *sample_name = format_span_name(name, &visitor.fields);
+            *fields = visitor.fields;
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(sample) = span.extensions().get::<SpanSample>() {
+                sample.begin();
+                ENTERED.with(|entered| entered.borrow_mut().push(id.clone()));
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<S>) {
+        let ended = ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+
+            // Spans that were already ended by an out of order exit aren't found
+            let index = entered.iter().rposition(|entered_id| entered_id == id)?;
+            let ended = entered.len() - index;
+
+            for _ in 0..ended {
+                Remotery::end_cpu_sample();
+            }
+
+            entered.truncate(index);
+            Some(ended)
+        });
+
+        if ended.is_some_and(|ended| ended > 1) {
+            let name = ctx.span(id).map_or("?", |span| span.name());
+            Remotery::log_text(&format!("Remotery: span \"{}\" exited before the spans entered inside it", name));
+        }
+    }
+
+    fn on_event(&self, event: &Event, _ctx: Context<S>) {
+        let metadata = event.metadata();
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        Remotery::log_text(&format_event_line(metadata.level().as_str(), metadata.target(), &visitor.message, &visitor.fields));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tracing;
+
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn test_format_span_name() {
+        assert_eq!(format_span_name("load", ""), "load");
+        assert_eq!(format_span_name("load", "path=\"a.map\" size=3"), "load{path=\"a.map\" size=3}");
+    }
+
+    #[test]
+    fn test_format_event_line() {
+        assert_eq!(format_event_line("INFO", "game::net", "connected", ""), "[INFO game::net] connected");
+        assert_eq!(format_event_line("WARN", "game", "slow frame", "ms=20"), "[WARN game] slow frame ms=20");
+        assert_eq!(format_event_line("DEBUG", "game", "", "ms=20"), "[DEBUG game] ms=20");
+    }
+
+    #[test]
+    fn test_spans_without_instance() {
+        let subscriber = tracing_subscriber::registry()
+            .with(RemoteryLayer::new().with_span_fields(true));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("outer", frame = 1, name = tracing::field::Empty);
+            let _outer = span.enter();
+            span.record("name", "test");
+
+            tracing::info!(count = 3, "inner event");
+            tracing::info_span!("inner").in_scope(|| {});
+        });
+    }
+
+    #[cfg(feature = "enabled")]
+    fn capture_spans<F: FnOnce()>(f: F) -> Vec<::sample_tree::SampleTree> {
+        let subscriber = tracing_subscriber::registry().with(RemoteryLayer::new());
+        ::testing::capture(|| tracing::subscriber::with_default(subscriber, f))
+    }
+
+    #[test]
+    #[cfg(feature = "enabled")]
+    fn test_spans_are_nested() {
+        let trees = capture_spans(|| {
+            tracing::info_span!("outer").in_scope(|| {
+                tracing::info_span!("inner").in_scope(|| {});
+                tracing::info_span!("inner").in_scope(|| {});
+            });
+        });
+
+        assert_eq!(::testing::outline(&trees), "outer\n  inner\n  inner\n");
+    }
+
+    #[test]
+    #[cfg(feature = "enabled")]
+    fn test_out_of_order_exit_ends_inner_spans() {
+        let trees = capture_spans(|| {
+            let outer = tracing::info_span!("outer").entered();
+            let inner = tracing::info_span!("inner").entered();
+
+            // Ends both samples, so the exit of inner below must not end anything
+            drop(outer);
+            tracing::info_span!("sibling").in_scope(|| {});
+            drop(inner);
+
+            tracing::info_span!("last").in_scope(|| {});
+        });
+
+        assert_eq!(::testing::outline(&trees), "outer\n  inner\nsibling\nlast\n");
+    }
+}
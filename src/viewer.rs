@@ -0,0 +1,97 @@
+//! Serves the Remotery web viewer over plain HTTP on the same port as the WebSocket server, so
+//! opening ``http://host:port/`` in a browser loads a viewer matching the linked C lib.
+//!
+//! The files are embedded at build time from ``external/remotery/vis``, which is kept in step
+//! with the protocol of ``external/remotery/lib``, or from the directory in the
+//! ``REMOTERY_VIS_DIR`` environment variable.
+//!
+use std::os::raw::{c_char, c_uint, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+use std::str;
+
+// Generated by build.rs as ``static ASSETS: &[(&str, &[u8])]`` with paths such as "/index.html"
+include!(concat!(env!("OUT_DIR"), "/viewer_assets.rs"));
+
+// Content types as C strings as they are handed straight to the HTTP response
+fn content_type(path: &str) -> &'static [u8] {
+    match path.rsplit('.').next() {
+        Some("html") | Some("htm") => b"text/html; charset=utf-8\0",
+        Some("js") => b"application/javascript\0",
+        Some("css") => b"text/css\0",
+        Some("json") => b"application/json\0",
+        Some("png") => b"image/png\0",
+        Some("gif") => b"image/gif\0",
+        Some("svg") => b"image/svg+xml\0",
+        Some("ico") => b"image/x-icon\0",
+        Some("txt") => b"text/plain; charset=utf-8\0",
+        _ => b"application/octet-stream\0",
+    }
+}
+
+///
+/// Looks up the file for an HTTP request path and returns its content type (nul-terminated)
+/// and contents. ``/`` maps to ``/index.html``.
+///
+pub fn find(path: &str) -> Option<(&'static [u8], &'static [u8])> {
+    let path = if path == "/" { "/index.html" } else { path };
+
+    ASSETS.binary_search_by(|&(name, _)| name.cmp(path)).ok().map(|index| (content_type(path), ASSETS[index].1))
+}
+
+///
+/// Called by the Remotery server thread for each plain HTTP GET on the server port. Files are
+/// static so nothing needs to be kept alive for the C side between calls.
+///
+pub unsafe extern "C" fn http_callback(_context: *mut c_void, path: *const c_char, path_length: c_uint,
+                                       content_type: *mut *const c_char, data: *mut *const c_void, length: *mut c_uint) -> c_uint {
+    if path.is_null() {
+        return 0;
+    }
+
+    let path = slice::from_raw_parts(path as *const u8, path_length as usize);
+
+    let found = panic::catch_unwind(AssertUnwindSafe(|| str::from_utf8(path).ok().and_then(find)));
+
+    match found {
+        Ok(Some((file_type, file))) => {
+            *content_type = file_type.as_ptr() as *const c_char;
+            *data = file.as_ptr() as *const c_void;
+            *length = file.len() as c_uint;
+            1
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_is_served_for_root() {
+        let (file_type, file) = find("/").unwrap();
+
+        assert_eq!(file_type, b"text/html; charset=utf-8\0");
+        assert_eq!(file, find("/index.html").unwrap().1);
+        assert!(find("/missing.js").is_none());
+    }
+
+    #[test]
+    fn test_viewer_is_embedded() {
+        let (_, index) = find("/index.html").unwrap();
+        let index = str::from_utf8(index).unwrap();
+
+        // Everything the page loads has to be embedded as well
+        for asset in &["Code/Viewer.js", "Styles/Viewer.css"] {
+            assert!(index.contains(asset));
+            assert!(find(&format!("/{}", asset)).is_some());
+        }
+    }
+
+    #[test]
+    fn test_content_types() {
+        assert_eq!(content_type("/Code/Remotery.js"), b"application/javascript\0");
+        assert_eq!(content_type("/Styles/Fonts/Font.woff"), b"application/octet-stream\0");
+    }
+}
@@ -0,0 +1,27 @@
+// Runs in its own process as there can only be one Remotery instance at a time
+extern crate remotery;
+
+use std::io;
+use std::net::SocketAddr;
+use remotery::client::Client;
+use remotery::{Remotery, RemoterySettings};
+
+#[test]
+fn test_viewers_need_the_access_token() {
+    let settings = RemoterySettings::new()
+        .port(0)
+        .limit_connections_to_localhost(true)
+        .access_token("s3cret-token");
+
+    let remotery = Remotery::create_global_instance_with(settings).unwrap();
+    let addr = SocketAddr::from(([127, 0, 0, 1], remotery.local_addr().port()));
+
+    let error = Client::connect(addr).err().expect("connected without a token");
+    assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+
+    let error = Client::connect_with_token(addr, "wrong-token").err().expect("connected with the wrong token");
+    assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+
+    let client = Client::connect_with_token(addr, "s3cret-token").unwrap();
+    assert_eq!(client.peer_addr(), addr);
+}
@@ -0,0 +1,29 @@
+// Runs in its own process as there can only be one Remotery instance at a time
+extern crate remotery;
+
+use std::net::{IpAddr, TcpListener};
+use remotery::client::Client;
+use remotery::{Remotery, RemoterySettings};
+
+// Creates an instance bound to ``ip`` on a port picked by the OS and connects a client to it
+fn connect_to_local_addr(ip: &str) {
+    let ip: IpAddr = ip.parse().unwrap();
+    let remotery = Remotery::create_global_instance_with(RemoterySettings::new().bind_address(ip).port(0)).unwrap();
+    let addr = remotery.local_addr();
+
+    assert_eq!(addr.ip(), ip);
+    assert_ne!(addr.port(), 0);
+
+    let client = Client::connect(addr).unwrap();
+    assert_eq!(client.peer_addr(), addr);
+}
+
+#[test]
+fn test_local_addr_is_the_bound_address() {
+    connect_to_local_addr("127.0.0.1");
+
+    // Not every machine has IPv6 loopback
+    if TcpListener::bind("[::1]:0").is_ok() {
+        connect_to_local_addr("::1");
+    }
+}
@@ -0,0 +1,45 @@
+// Runs in its own process as there can only be one Remotery instance at a time
+extern crate remotery;
+
+use std::fs::{self, File};
+use std::thread;
+use std::time::Duration;
+use remotery::capture::{self, CaptureMode, Record};
+use remotery::{Remotery, RemoterySettings, SampleFlags};
+
+#[test]
+fn test_queued_records_are_written_on_drop() {
+    let path = std::env::temp_dir().join(format!("remotery_capture_file_test_{}.jsonl", std::process::id()));
+
+    // The Remotery thread sleeps through the whole test so everything is still queued on drop
+    let settings = RemoterySettings::new()
+        .port(0)
+        .ms_sleep_between_server_updates(2000)
+        .capture_mode(CaptureMode::File(path.clone()));
+    let remotery = Remotery::create_global_instance_with(settings).unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    Remotery::set_current_thread_name("capture_file");
+    Remotery::begin_cpu_sample("last_sample", SampleFlags::Default);
+    Remotery::end_cpu_sample();
+    Remotery::log_text("last line");
+    Remotery::mark_frame();
+
+    drop(remotery);
+
+    let records: Vec<Record> = capture::read(File::open(&path).unwrap()).map(|r| r.unwrap()).collect();
+    let _ = fs::remove_file(&path);
+
+    assert!(records.iter().any(|record| match *record {
+        Record::Samples { ref thread_name, ref samples, .. } => thread_name == "capture_file" && samples[0].name == "last_sample",
+        _ => false,
+    }), "sample tree missing from {:?}", records);
+    assert!(records.iter().any(|record| match *record {
+        Record::Log { ref text, .. } => text == "last line",
+        _ => false,
+    }), "log line missing from {:?}", records);
+    assert!(records.iter().any(|record| match *record {
+        Record::Frame { frame_index, .. } => frame_index == 0,
+        _ => false,
+    }), "frame missing from {:?}", records);
+}
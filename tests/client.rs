@@ -0,0 +1,52 @@
+// Runs in its own process as there can only be one Remotery instance at a time
+extern crate remotery;
+
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use remotery::client::{Client, Message};
+use remotery::{Remotery, RemoterySettings, SampleFlags};
+
+#[test]
+fn test_client_receives_messages_and_sends_console_input() {
+    let settings = RemoterySettings::new()
+        .port(0)
+        .limit_connections_to_localhost(true)
+        .ms_sleep_between_server_updates(1);
+
+    let mut remotery = Remotery::create_global_instance_with(settings).unwrap();
+
+    let (sender, inputs) = mpsc::channel();
+    let sender = Mutex::new(sender);
+    remotery.set_input_handler(move |text| {
+        let _ = sender.lock().unwrap().send(text.to_owned());
+    });
+
+    let mut client = Client::connect(("127.0.0.1", remotery.local_addr().port())).unwrap();
+    client.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let (mut got_samples, mut got_log, mut got_frame) = (false, false, false);
+
+    // Anything sent before the server has added the client is dropped so keep sending
+    while !(got_samples && got_log && got_frame) {
+        assert!(Instant::now() < deadline, "timed out waiting for SAMPLES, LOG and FRAME");
+
+        Remotery::begin_cpu_sample("client_test", SampleFlags::Default);
+        Remotery::end_cpu_sample();
+        Remotery::log_text("hello from the client test");
+        Remotery::mark_frame();
+
+        // The Remotery thread's own samples keep arriving so only read one message per pass
+        match client.receive() {
+            Ok(Message::Samples { ref samples, .. }) if samples[0].name == "client_test" => got_samples = true,
+            Ok(Message::Log { ref text }) if text == "hello from the client test" => got_log = true,
+            Ok(Message::Frame { .. }) => got_frame = true,
+            _ => (),
+        }
+    }
+
+    client.send_console_input("reload shaders").unwrap();
+
+    assert_eq!(inputs.recv_timeout(Duration::from_secs(10)).unwrap(), "reload shaders");
+}
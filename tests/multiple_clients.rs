@@ -0,0 +1,67 @@
+// Runs in its own process as there can only be one Remotery instance at a time
+extern crate remotery;
+
+use std::time::{Duration, Instant};
+use remotery::client::{Client, Message};
+use remotery::{Remotery, RemoterySettings, SampleFlags};
+
+// Sends ``name`` samples until ``client`` receives one, skipping anything else the server sends
+fn receive_sample(client: &mut Client, name: &str) -> Message {
+    let deadline = Instant::now() + Duration::from_secs(10);
+
+    loop {
+        assert!(Instant::now() < deadline, "timed out waiting for {}", name);
+
+        Remotery::begin_cpu_sample(name, SampleFlags::Default);
+        Remotery::end_cpu_sample();
+
+        if let Ok(message) = client.receive() {
+            let found = match message {
+                Message::Samples { ref samples, .. } => samples[0].name == name,
+                _ => false,
+            };
+
+            if found {
+                return message;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_clients_receive_the_same_samples() {
+    let settings = RemoterySettings::new()
+        .port(0)
+        .limit_connections_to_localhost(true)
+        .max_clients(2)
+        .ms_sleep_between_server_updates(1);
+
+    let remotery = Remotery::create_global_instance_with(settings).unwrap();
+    let addr = ("127.0.0.1", remotery.local_addr().port());
+
+    let mut first = Client::connect(addr).unwrap();
+    let mut second = Client::connect(addr).unwrap();
+
+    for client in [&mut first, &mut second] {
+        client.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    }
+
+    // Anything sent before the server has added a client is dropped so wait until both get samples
+    receive_sample(&mut first, "warm_up");
+    receive_sample(&mut second, "warm_up");
+
+    let message = receive_sample(&mut first, "broadcast_test");
+
+    // The second client gets the same tree unless it fell behind and got a later one
+    let deadline = Instant::now() + Duration::from_secs(10);
+
+    loop {
+        assert!(Instant::now() < deadline, "the second client never received {:?}", message);
+
+        if let Ok(received) = second.receive() {
+            if received == message {
+                break;
+            }
+        }
+    }
+}
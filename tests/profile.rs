@@ -0,0 +1,52 @@
+// Runs in its own process as remotery::testing keeps a Remotery instance for the whole run
+#[macro_use]
+extern crate remotery;
+
+use remotery::profile;
+use remotery::testing;
+
+struct World<T>(T);
+
+trait System {
+    fn run(&self);
+}
+
+struct Physics;
+struct Audio;
+
+impl<T> World<T> {
+    #[profile]
+    fn update(&self, systems: &[&dyn System]) {
+        for system in systems {
+            system.run();
+        }
+    }
+}
+
+impl System for Physics {
+    #[profile]
+    fn run(&self) {
+        step();
+    }
+}
+
+impl System for Audio {
+    #[profile]
+    fn run(&self) {}
+}
+
+#[profile(name = "physics step", aggregate)]
+fn step() {}
+
+#[profile]
+fn tick<T>(world: &World<T>) {
+    world.update(&[&Physics, &Audio]);
+}
+
+#[test]
+fn test_profile_names_methods_after_their_self_type() {
+    let trees = testing::capture(|| tick(&World(1u8)));
+
+    assert_sample!(trees, "profile::tick/profile::World<_>::update/<profile::Physics as profile::System>::run/physics step", count == 1);
+    assert_sample!(trees, "profile::tick/profile::World<_>::update/<profile::Audio as profile::System>::run", count == 1);
+}